@@ -0,0 +1,159 @@
+use crate::{Error, Operation};
+use std::{fs, io::Seek, path::Path, time::Duration};
+
+/// format of an audio file, read by `probe()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioInfo {
+    pub duration: Duration,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// mp3などでは`None`
+    pub bits_per_sample: Option<u16>,
+}
+
+/// read the format of an audio file.
+/// WAVはヘッダから読む。それ以外の形式は`decode` featureが必要。
+/// 読めない、途中で切れているファイルは`Error::InvalidAudio`。
+/// `decode` featureが無い場合、WAV以外は`Error::UnsupportedAudio`
+pub fn probe<P: AsRef<Path>>(path: P) -> Result<AudioInfo, Error> {
+    let path = path.as_ref();
+    let is_wav = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("wav"));
+    match is_wav {
+        true => probe_wav(path),
+        false => probe_other(path),
+    }
+}
+
+fn probe_wav(path: &Path) -> Result<AudioInfo, Error> {
+    let invalid = |msg: String| Error::InvalidAudio(path.to_path_buf(), msg);
+    // バッファしない`File`で開き、ヘッダを読んだ後の位置をデータの開始位置にする
+    let file = fs::File::open(path).map_err(Error::io(Operation::Read, path))?;
+    let reader = hound::WavReader::new(file).map_err(|e| invalid(e.to_string()))?;
+    let spec = reader.spec();
+    if spec.sample_rate == 0 || spec.channels == 0 {
+        return Err(invalid("sample rate or channels is 0".to_string()));
+    }
+    // duration()は1チャンネルあたりのサンプル数。ヘッダの値なので実際のサイズと比べる
+    let frames = reader.duration() as u64;
+    let data_bytes = frames * spec.channels as u64 * spec.bits_per_sample.div_ceil(8) as u64;
+    let mut file = reader.into_inner();
+    let data_start = file
+        .stream_position()
+        .map_err(Error::io(Operation::Read, path))?;
+    let file_bytes = file
+        .metadata()
+        .map_err(Error::io(Operation::Read, path))?
+        .len();
+    let available = file_bytes.saturating_sub(data_start);
+    if available < data_bytes {
+        return Err(invalid(format!(
+            "truncated ({} bytes of samples, but the header says {} bytes)",
+            available, data_bytes
+        )));
+    }
+    Ok(AudioInfo {
+        duration: Duration::from_secs_f64(frames as f64 / spec.sample_rate as f64),
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        bits_per_sample: Some(spec.bits_per_sample),
+    })
+}
+
+#[cfg(not(feature = "decode"))]
+fn probe_other(path: &Path) -> Result<AudioInfo, Error> {
+    Err(Error::UnsupportedAudio(path.to_path_buf()))
+}
+
+#[cfg(feature = "decode")]
+fn probe_other(path: &Path) -> Result<AudioInfo, Error> {
+    use symphonia::core::{
+        formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+    };
+
+    let invalid = |msg: String| Error::InvalidAudio(path.to_path_buf(), msg);
+    let file = fs::File::open(path).map_err(Error::io(Operation::Read, path))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension() {
+        hint.with_extension(&ext.to_string_lossy());
+    }
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| invalid(e.to_string()))?
+        .format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| invalid("no audio track".to_string()))?;
+    let params = track.codec_params.clone();
+    let track_id = track.id;
+    let sample_rate = params
+        .sample_rate
+        .filter(|r| *r > 0)
+        .ok_or_else(|| invalid("unknown sample rate".to_string()))?;
+
+    // ヘッダに長さが無い場合はパケットを数える
+    let frames = match params.n_frames {
+        Some(n) => n,
+        None => {
+            let mut frames = 0;
+            while let Ok(packet) = format.next_packet() {
+                if packet.track_id() == track_id {
+                    frames += packet.dur;
+                }
+            }
+            frames
+        }
+    };
+    Ok(AudioInfo {
+        duration: Duration::from_secs_f64(frames as f64 / sample_rate as f64),
+        sample_rate,
+        channels: params.channels.map(|c| c.count() as u16).unwrap_or(1),
+        bits_per_sample: params.bits_per_sample.map(|b| b as u16),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::ready_in;
+
+    #[test]
+    fn wav_and_broken() {
+        let dir = ready_in("audio_probe");
+        let info = probe(dir.join("Talk1_1.wav")).unwrap();
+        assert_eq!(info.sample_rate, 48000);
+        assert_eq!(info.channels, 1);
+        assert_eq!(info.bits_per_sample, Some(16));
+        assert!(info.duration > Duration::from_secs(2));
+
+        // 0バイト、途中で切れたファイル
+        let bytes = fs::read(dir.join("Talk1_1.wav")).unwrap();
+        fs::write(dir.join("truncated.wav"), &bytes[..bytes.len() / 2]).unwrap();
+        // ヘッダの分より少なく切った場合
+        fs::write(dir.join("short.wav"), &bytes[..bytes.len() - 40]).unwrap();
+        for name in ["non_line.wav", "truncated.wav", "short.wav"] {
+            let e = probe(dir.join(name));
+            println!("{:?}", e);
+            assert!(matches!(e, Err(Error::InvalidAudio(..))));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn mp3() {
+        let dir = ready_in("audio_probe_mp3");
+        let info = probe(dir.join("english_test.mp3")).unwrap();
+        println!("{:?}", info);
+        assert!(info.sample_rate > 0);
+        assert!(info.duration > Duration::ZERO);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// how to resolve two or more entries that have the same new file name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    /// `name_1.wav`, `name_2.wav`...
+    #[default]
+    NumericSuffix,
+    /// `3_name.wav` (3は`{index}`と同じ、1から始まるリスト内の番号)
+    IndexPrefix,
+    /// `name_1a2b3c4d.wav` (元のファイル名のhash)
+    HashSuffix,
+    /// 解決せず、`rename()`をエラーにする
+    Abort,
+}
+
+/// a new file name claimed by more than one entry, or already existing in the output folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    pub target: PathBuf,
+    pub sources: Vec<PathBuf>,
+    /// 出力フォルダに既に同名のファイルがあった
+    pub existing: bool,
+    /// policyによって別の名前に変更された
+    pub resolved: bool,
+}
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sources = self
+            .sources
+            .iter()
+            .filter_map(|p| p.file_name().map(|f| f.to_string_lossy().to_string()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(
+            f,
+            "! collision{}{}: {} <--- {}",
+            if self.existing {
+                " (already exists)"
+            } else {
+                ""
+            },
+            if self.resolved { " (resolved)" } else { "" },
+            self.target
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            sources
+        )
+    }
+}
+
+/// ファイル名の重複判定用のキー。WindowsとmacOSは大文字小文字を区別しないため合わせる
+pub(crate) fn key(file_name: &str) -> String {
+    file_name.to_lowercase()
+}
+
+/// return a file name that is not taken according to `policy`.
+/// `Abort`の場合はそのまま返す
+pub(crate) fn resolve<F: Fn(&str) -> bool>(
+    policy: CollisionPolicy,
+    stem: &str,
+    extension: &str,
+    index: usize,
+    source: &Path,
+    is_taken: F,
+) -> String {
+    let file_name = |s: &str| format!("{}.{}", s, extension);
+    let first = match policy {
+        CollisionPolicy::Abort => return file_name(stem),
+        CollisionPolicy::NumericSuffix => None,
+        CollisionPolicy::IndexPrefix => Some(format!("{}_{}", index, stem)),
+        CollisionPolicy::HashSuffix => Some(format!("{}_{:08x}", stem, hash(source))),
+    };
+    if let Some(v) = first.map(|s| file_name(&s)) {
+        if !is_taken(&v) {
+            return v;
+        }
+    }
+    // それでも重複する場合は連番にする
+    (1..)
+        .map(|n| file_name(&format!("{}_{}", stem, n)))
+        .find(|v| !is_taken(v))
+        .unwrap_or_default()
+}
+
+/// 元のファイル名のFNV-1a hash。実行環境によって値が変わらないように自前で実装
+fn hash(source: &Path) -> u32 {
+    source
+        .file_name()
+        .unwrap_or(source.as_os_str())
+        .to_string_lossy()
+        .bytes()
+        .fold(0x811c9dc5, |h, b| (h ^ b as u32).wrapping_mul(0x01000193))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn resolve_policies() {
+        let source = PathBuf::from("Talk1_1.wav");
+        let mut taken = HashSet::new();
+        taken.insert(key("Line.wav"));
+        taken.insert(key("line_1.wav"));
+
+        let r = |p| resolve(p, "line", "wav", 3, &source, |n| taken.contains(&key(n)));
+        assert_eq!(r(CollisionPolicy::NumericSuffix), "line_2.wav");
+        assert_eq!(r(CollisionPolicy::IndexPrefix), "3_line.wav");
+        assert_eq!(r(CollisionPolicy::Abort), "line.wav");
+        assert_eq!(
+            r(CollisionPolicy::HashSuffix),
+            format!("line_{:08x}.wav", hash(&source))
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{
+    timeline::{Clip, Timeline},
+    Error, Operation,
+};
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// join the clips of `timeline` into one WAV file, with silence of the gaps between them.
+/// サンプルレート、チャンネル数、整数か浮動小数点かが1つ目のクリップと違うものがあった場合は、
+/// 何も書かずに`Error::MismatchedAudio`を返す。ビット深度の違いは1つ目に合わせる。
+/// 出力したファイル上の位置に直した`Timeline`を返す(`to_cue()`, `to_labels()`用)
+pub fn concat_wav<P: AsRef<Path>>(timeline: &Timeline, output: P) -> Result<Timeline, Error> {
+    let output = output.as_ref();
+    let invalid = |path: &Path| {
+        let path = path.to_path_buf();
+        move |e: hound::Error| Error::InvalidAudio(path, e.to_string())
+    };
+
+    // 先に全て開いて形式を確認する
+    let mut readers = Vec::new();
+    for c in &timeline.clips {
+        let path = readable_path(c);
+        let reader = hound::WavReader::open(&path).map_err(invalid(&path))?;
+        readers.push((path, reader));
+    }
+    let spec = match readers.first() {
+        Some((_, r)) => r.spec(),
+        None => return Ok(Timeline::default()),
+    };
+    let mismatched = readers
+        .iter()
+        .filter(|(_, r)| {
+            let s = r.spec();
+            s.sample_rate != spec.sample_rate
+                || s.channels != spec.channels
+                || s.sample_format != spec.sample_format
+        })
+        .map(|(p, _)| p.clone())
+        .collect::<Vec<_>>();
+    if !mismatched.is_empty() {
+        return Err(Error::MismatchedAudio(mismatched));
+    }
+
+    let result = write_wav(timeline, readers, spec, output);
+    if result.is_err() {
+        // 途中まで書いたファイルは残さない
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+fn write_wav(
+    timeline: &Timeline,
+    readers: Vec<(PathBuf, hound::WavReader<std::io::BufReader<fs::File>>)>,
+    spec: hound::WavSpec,
+    output: &Path,
+) -> Result<Timeline, Error> {
+    let write_err = |e: hound::Error| match e {
+        hound::Error::IoError(e) => Error::io(Operation::Write, output)(e),
+        e => Error::InvalidAudio(output.to_path_buf(), e.to_string()),
+    };
+    let rate = spec.sample_rate as f64;
+    let channels = spec.channels as u64;
+    let mut writer = hound::WavWriter::create(output, spec).map_err(write_err)?;
+    let mut joined = Timeline::default();
+    // 1チャンネルあたりのサンプル数で数える
+    let mut position: u64 = 0;
+    let mut prev_end = Duration::ZERO;
+
+    for (c, (path, mut reader)) in timeline.clips.iter().zip(readers) {
+        let silence = (c.start.saturating_sub(prev_end).as_secs_f64() * rate).round() as u64;
+        prev_end = c.end();
+        for _ in 0..silence * channels {
+            match spec.sample_format {
+                hound::SampleFormat::Float => writer.write_sample(0.0f32),
+                hound::SampleFormat::Int => writer.write_sample(0i32),
+            }
+            .map_err(write_err)?;
+        }
+        position += silence;
+
+        let read_err = |e: hound::Error| Error::InvalidAudio(path.clone(), e.to_string());
+        let bits = reader.spec().bits_per_sample as i32 - spec.bits_per_sample as i32;
+        let mut samples = 0;
+        match spec.sample_format {
+            hound::SampleFormat::Float => {
+                for s in reader.samples::<f32>() {
+                    writer
+                        .write_sample(s.map_err(read_err)?)
+                        .map_err(write_err)?;
+                    samples += 1;
+                }
+            }
+            hound::SampleFormat::Int => {
+                for s in reader.samples::<i32>() {
+                    let s = s.map_err(read_err)?;
+                    // 1つ目のクリップのビット深度に合わせる
+                    let s = match bits {
+                        b if b > 0 => s >> b,
+                        b => s << -b,
+                    };
+                    writer.write_sample(s).map_err(write_err)?;
+                    samples += 1;
+                }
+            }
+        }
+        let frames = samples / channels;
+        joined.clips.push(Clip {
+            start: Duration::from_secs_f64(position as f64 / rate),
+            duration: Duration::from_secs_f64(frames as f64 / rate),
+            ..c.clone()
+        });
+        position += frames;
+    }
+    writer.finalize().map_err(write_err)?;
+    Ok(joined)
+}
+
+/// rename前は元のパス、rename後は変更後のパス
+fn readable_path(clip: &Clip) -> PathBuf {
+    match clip.source.exists() {
+        true => clip.source.clone(),
+        false => clip.path.clone(),
+    }
+}
+
+/// cue sheet for `wav_name`. 1クリップ1トラックで、セリフをTITLE、話者をPERFORMERにする。
+/// 仕様上トラックは99までだが、それ以上も書き出す
+pub fn to_cue(timeline: &Timeline, wav_name: &str) -> String {
+    let mut out = format!("FILE \"{}\" WAVE\n", cue_text(wav_name));
+    for (n, c) in timeline.clips.iter().enumerate() {
+        let _ = writeln!(out, "  TRACK {:02} AUDIO", n + 1);
+        let title = match c.line.trim() {
+            "" => c
+                .path
+                .file_stem()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            line => line.to_string(),
+        };
+        let _ = writeln!(out, "    TITLE \"{}\"", cue_text(&title));
+        if let Some(s) = &c.speaker {
+            let _ = writeln!(out, "    PERFORMER \"{}\"", cue_text(s));
+        }
+        // 1秒は75フレーム
+        let frames = (c.start.as_secs_f64() * 75.0).round() as u64;
+        let _ = writeln!(
+            out,
+            "    INDEX 01 {:02}:{:02}:{:02}",
+            frames / 75 / 60,
+            frames / 75 % 60,
+            frames % 75
+        );
+    }
+    out
+}
+
+/// Audacity label track (`開始\t終了\tセリフ`, 秒).
+pub fn to_labels(timeline: &Timeline) -> String {
+    let mut out = String::new();
+    for c in &timeline.clips {
+        let _ = writeln!(
+            out,
+            "{:.6}\t{:.6}\t{}",
+            c.start.as_secs_f64(),
+            c.end().as_secs_f64(),
+            c.line.split_whitespace().collect::<Vec<_>>().join(" ")
+        );
+    }
+    out
+}
+
+/// ファイルを上書きで保存する。FILEには`wav`のファイル名を書く
+pub fn save_cue<P: AsRef<Path>, Q: AsRef<Path>>(
+    timeline: &Timeline,
+    wav: P,
+    path: Q,
+) -> Result<(), Error> {
+    let wav_name = wav
+        .as_ref()
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+    fs::write(&path, to_cue(timeline, &wav_name)).map_err(Error::io(Operation::Write, &path))
+}
+
+/// ファイルを上書きで保存する
+pub fn save_labels<P: AsRef<Path>>(timeline: &Timeline, path: P) -> Result<(), Error> {
+    fs::write(&path, to_labels(timeline)).map_err(Error::io(Operation::Write, &path))
+}
+
+/// CUEでは`"`をエスケープできず、改行も使えないため
+fn cue_text(s: &str) -> String {
+    s.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('"', "'")
+}
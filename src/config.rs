@@ -17,10 +17,19 @@ pub enum Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::FailedGetHome => writeln!(f, "failed get home dir"),
-            Error::IoErr(error) => writeln!(f, "io error: {}", error),
-            Error::FailedToString => writeln!(f, "failed convert to string from Config struct"),
-            Error::FailedSave => writeln!(f, "failed save Config"),
+            Error::FailedGetHome => write!(f, "failed get home dir"),
+            Error::IoErr(error) => write!(f, "io error: {}", error),
+            Error::FailedToString => write!(f, "failed convert to string from Config struct"),
+            Error::FailedSave => write!(f, "failed save Config"),
+        }
+    }
+}
+/// `track2line_lib::Error::Config`としても返せる
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IoErr(e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -0,0 +1,73 @@
+use std::{fmt, path::PathBuf};
+
+/// kind of a problem found by `PathSets::validate()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// 対応する音声ファイルが無いテキストファイル
+    OrphanText,
+    /// セリフが取れず`empty_N`になる音声ファイル(テキストファイルが無い)
+    MissingText,
+    /// テキストファイルが空
+    EmptyText,
+    /// ファイル名の前後に空白がある、または空白のみ(`include_whs_end .wav`など)
+    WhitespaceStem,
+    /// `.wav`, `._Talk1_1.wav`のような隠しファイル
+    HiddenFile,
+    /// 読めない、途中で切れている音声ファイル。理由を持つ
+    UnreadableAudio(String),
+    /// `decode` featureが無いため長さや形式を読めない音声ファイル(mp3など)
+    UnsupportedAudio,
+    /// 変更後のファイル名が重複している。`resolved`が`false`の場合は`rename()`がエラーになる
+    DuplicateTarget { target: PathBuf, resolved: bool },
+    /// セリフが`Truncation`の長さを超えていて、ファイル名では切られる
+    LongLine { length: usize, limit: usize },
+}
+
+/// one problem found by `PathSets::validate()`. `path`は元のファイル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub kind: DiagnosticKind,
+}
+impl Diagnostic {
+    /// `rename()`が失敗するものはtrue。それ以外は警告
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self.kind,
+            DiagnosticKind::DuplicateTarget {
+                resolved: false,
+                ..
+            }
+        )
+    }
+}
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path.display();
+        match &self.kind {
+            DiagnosticKind::OrphanText => write!(f, "no audio file for {}", path),
+            DiagnosticKind::MissingText => write!(f, "no line for {}", path),
+            DiagnosticKind::EmptyText => write!(f, "empty line file for {}", path),
+            DiagnosticKind::WhitespaceStem => {
+                write!(f, "leading or trailing whitespace in \"{}\"", path)
+            }
+            DiagnosticKind::HiddenFile => write!(f, "hidden file {}", path),
+            DiagnosticKind::UnreadableAudio(reason) => {
+                write!(f, "unreadable audio {}: {}", path, reason)
+            }
+            DiagnosticKind::UnsupportedAudio => {
+                write!(f, "unsupported audio format {} (needs `decode`)", path)
+            }
+            DiagnosticKind::DuplicateTarget { target, resolved } => write!(
+                f,
+                "{} duplicates {}{}",
+                path,
+                target.display(),
+                if *resolved { " (resolved)" } else { "" }
+            ),
+            DiagnosticKind::LongLine { length, limit } => {
+                write!(f, "line of {} is cut ({} > {})", path, length, limit)
+            }
+        }
+    }
+}
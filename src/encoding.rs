@@ -0,0 +1,92 @@
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_16BE, UTF_16LE};
+
+/// return the encoding for a label such as `"shift_jis"`, `"utf-16le"` or `"euc-jp"`.
+pub(crate) fn for_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+/// decode a line file. BOMは取り除く。
+/// BOM、BOM無しのUTF-16、UTF-8、`fallback`、Shift_JISの順に試し、
+/// どれでもデコードできない場合は`fallback`で置換文字を含めてデコードする
+pub(crate) fn decode(bytes: &[u8], fallback: &'static Encoding) -> String {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        return encoding
+            .decode_without_bom_handling(&bytes[bom_len..])
+            .0
+            .into_owned();
+    }
+    // ASCIIのUTF-16はUTF-8としても有効なため先に見る
+    let utf16 = guess_utf16(bytes);
+    if utf16.is_none() {
+        if let Ok(v) = std::str::from_utf8(bytes) {
+            return v.to_string();
+        }
+    }
+
+    let candidates = [utf16, Some(fallback), Some(SHIFT_JIS)];
+    candidates
+        .into_iter()
+        .flatten()
+        .find_map(|e| e.decode_without_bom_handling_and_without_replacement(bytes))
+        .map(|v| v.into_owned())
+        .unwrap_or_else(|| fallback.decode_without_bom_handling(bytes).0.into_owned())
+}
+
+/// ASCIIの多いテキストのUTF-16では、片方のバイトが0になることが多い
+fn guess_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let pairs = bytes.len() / 2;
+    let zeros_at = |offset: usize| {
+        bytes
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|b| **b == 0)
+            .count()
+    };
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    // 半分以上が0ならUTF-16とみなす
+    if odd * 2 >= pairs && even == 0 {
+        Some(UTF_16LE)
+    } else if even * 2 >= pairs && odd == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// デフォルトのfallback
+pub(crate) fn default_fallback() -> &'static Encoding {
+    SHIFT_JIS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str, bom: bool) -> Vec<u8> {
+        let mut bytes = if bom { vec![0xFF, 0xFE] } else { vec![] };
+        bytes.extend(text.encode_utf16().flat_map(|u| u.to_le_bytes()));
+        bytes
+    }
+
+    #[test]
+    fn detect() {
+        let text = "これはテストです。";
+        let fallback = default_fallback();
+        assert_eq!(decode(text.as_bytes(), fallback), text);
+        assert_eq!(
+            decode(format!("\u{feff}{}", text).as_bytes(), fallback),
+            text
+        );
+        assert_eq!(decode(&SHIFT_JIS.encode(text).0, fallback), text);
+        assert_eq!(decode(&utf16le(text, true), fallback), text);
+        assert_eq!(decode(&utf16le("Talk 1", false), fallback), "Talk 1");
+
+        let euc = for_label("euc-jp").unwrap();
+        assert_eq!(decode(&euc.encode(text).0, euc), text);
+        assert!(for_label("unknown").is_none());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{
+    timeline::{frames, Timeline},
+    Error, Operation,
+};
+use encoding_rs::SHIFT_JIS;
+use std::{
+    fmt::Write,
+    fs,
+    path::{self, Path},
+};
+
+/// テキストオブジェクトの`text=`の長さ(16進数の文字数)
+const TEXT_HEX_LEN: usize = 4096;
+
+/// exporter of a `Timeline` as an AviUtl (拡張編集) object file.
+/// 話者ごとに音声とテキストのレイヤーを1組ずつ使う(話者1は1, 2、話者2は3, 4...)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExoExport {
+    width: u32,
+    height: u32,
+    frame_rate: u32,
+    audio_rate: u32,
+    font: String,
+    text_size: u32,
+}
+impl Default for ExoExport {
+    /// 1920x1080, 30fps, 48kHz、MS UI Gothicの34
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            frame_rate: 30,
+            audio_rate: 48000,
+            font: "MS UI Gothic".to_string(),
+            text_size: 34,
+        }
+    }
+}
+impl ExoExport {
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// 0の場合は1にする
+    pub fn set_frame_rate(&mut self, fps: u32) {
+        self.frame_rate = fps.max(1);
+    }
+
+    pub fn set_audio_rate(&mut self, rate: u32) {
+        self.audio_rate = rate;
+    }
+
+    pub fn set_font(&mut self, font: &str) {
+        self.font = font.to_string();
+    }
+
+    pub fn set_text_size(&mut self, size: u32) {
+        self.text_size = size;
+    }
+
+    /// `.exo`の内容。AviUtlが読めるようにShift_JIS、改行はCRLF。
+    /// ファイルパスやフォントにShift_JISで書けない文字がある場合は`Error::UnmappableText`。
+    /// 音声ファイルのパスは絶対パスにする
+    pub fn to_exo(&self, timeline: &Timeline) -> Result<Vec<u8>, Error> {
+        let fps = self.frame_rate;
+        let mut out = String::new();
+        let mut objects = Vec::<String>::new();
+        let mut length = 0;
+
+        for (c, track) in timeline.clips.iter().zip(timeline.speaker_tracks()) {
+            // 拡張編集のフレームは1から、endは含む
+            let start = frames(c.start, fps) + 1;
+            let end = (frames(c.end(), fps)).max(start);
+            length = length.max(end);
+            let layer = track * 2 + 1;
+            // 相対パスはAviUtlから解決できないため
+            let file = path::absolute(&c.path)
+                .unwrap_or(c.path.clone())
+                .display()
+                .to_string();
+            // 文字参照に置き換えられるとAviUtlが開けないため
+            if SHIFT_JIS.encode(&file).2 {
+                return Err(Error::UnmappableText(file));
+            }
+
+            objects.push(format!(
+                "start={start}\nend={end}\nlayer={layer}\noverlay=1\naudio=1\n\
+                 [N.0]\n_name=音声ファイル\n再生位置=0.00\n再生速度=100.0\nループ再生=0\n動画ファイルと連携=0\nfile={}\n\
+                 [N.1]\n_name=標準再生\n音量=100.0\n左右=0.0\n",
+                file
+            ));
+            if !c.line.trim().is_empty() {
+                objects.push(format!(
+                    "start={start}\nend={end}\nlayer={}\noverlay=1\ncamera=0\n\
+                     [N.0]\n_name=テキスト\nサイズ={}\n表示速度=0.0\n文字毎に個別オブジェクト=0\n移動座標上に表示する=0\n自動スクロール=0\n\
+                     B=0\nI=0\ntype=0\nautoadjust=0\nsoft=1\nmonospace=0\nalign=4\nspacing_x=0\nspacing_y=0\nprecision=1\n\
+                     color=ffffff\ncolor2=000000\nfont={}\ntext={}\n\
+                     [N.1]\n_name=標準描画\nX=0.0\nY=0.0\nZ=0.0\n拡大率=100.00\n透明度=0.0\n回転=0.00\nblend=0\n",
+                    layer + 1,
+                    self.text_size,
+                    self.font,
+                    text_hex(c.line.trim())
+                ));
+            }
+        }
+
+        let _ = write!(
+            out,
+            "[exedit]\nwidth={}\nheight={}\nrate={}\nscale=1\nlength={}\naudio_rate={}\naudio_ch=2\n",
+            self.width, self.height, fps, length, self.audio_rate
+        );
+        for (n, object) in objects.iter().enumerate() {
+            let _ = write!(
+                out,
+                "[{}]\n{}",
+                n,
+                object.replace("[N.", &format!("[{}.", n))
+            );
+        }
+        let out = out.replace('\n', "\r\n");
+        let (bytes, _, unmappable) = SHIFT_JIS.encode(&out);
+        if unmappable {
+            return Err(Error::UnmappableText(format!("font={}", self.font)));
+        }
+        Ok(bytes.into_owned())
+    }
+
+    /// ファイルを上書きで保存する
+    pub fn save_exo<P: AsRef<Path>>(&self, timeline: &Timeline, path: P) -> Result<(), Error> {
+        fs::write(&path, self.to_exo(timeline)?).map_err(Error::io(Operation::Write, &path))
+    }
+}
+
+/// テキストオブジェクトの`text=`。UTF-16LEの16進数を4096文字まで0で埋める
+fn text_hex(text: &str) -> String {
+    let mut hex = String::with_capacity(TEXT_HEX_LEN);
+    // 最後の1文字分(0000)は終端として残す
+    for unit in text.replace('\n', "\r\n").encode_utf16() {
+        if hex.len() + 4 >= TEXT_HEX_LEN {
+            break;
+        }
+        for b in unit.to_le_bytes() {
+            let _ = write!(hex, "{:02x}", b);
+        }
+    }
+    format!("{:0<width$}", hex, width = TEXT_HEX_LEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeline::Clip;
+    use std::{path::PathBuf, time::Duration};
+
+    #[test]
+    fn exo() {
+        let clip = |start: u64, line: &str, speaker: &str| Clip {
+            source: PathBuf::from("a.wav"),
+            path: PathBuf::from("C:\\voice\\a.wav"),
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(1000),
+            line: line.to_string(),
+            speaker: Some(speaker.to_string()),
+        };
+        let timeline = Timeline {
+            clips: vec![
+                clip(0, "こんにちは", "つくよみちゃん"),
+                clip(1500, "", "ずんだもん"),
+            ],
+        };
+        let bytes = ExoExport::default().to_exo(&timeline).unwrap();
+        let exo = SHIFT_JIS.decode(&bytes).0.replace("\r\n", "\n");
+        println!("{}", exo);
+        assert!(exo.starts_with("[exedit]\nwidth=1920\nheight=1080\nrate=30\nscale=1\nlength=75\n"));
+        assert!(exo.contains("[0]\nstart=1\nend=30\nlayer=1\n"));
+        assert!(exo.contains("[0.0]\n_name=音声ファイル\n"));
+        let file = path::absolute("C:\\voice\\a.wav").unwrap();
+        assert!(exo.contains(&format!("\nfile={}\n", file.display())));
+        assert!(exo.contains("[1]\nstart=1\nend=30\nlayer=2\n"));
+        // セリフが無いためテキストは無い
+        assert!(exo.contains("[2]\nstart=46\nend=75\nlayer=3\n"));
+        assert!(!exo.contains("[3]"));
+        assert!(exo.contains("text=533093306b3061306f30000000"));
+
+        // Shift_JISに無い文字
+        let mut timeline = timeline;
+        timeline.clips[1].path = PathBuf::from("/tmp/🎵ボイス/a.wav");
+        assert!(matches!(
+            ExoExport::default().to_exo(&timeline),
+            Err(Error::UnmappableText(t)) if t.contains("🎵ボイス")
+        ));
+
+        assert_eq!(text_hex("a").len(), TEXT_HEX_LEN);
+        assert!(text_hex(&"あ".repeat(2000)).ends_with("0000"));
+    }
+}
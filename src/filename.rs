@@ -0,0 +1,230 @@
+use crate::Error;
+use serde::{Deserialize, Serialize};
+
+/// パターンで使えるトークン一覧。`{_}`は読み飛ばす
+const TOKENS: [&str; 5] = ["index", "speaker", "style", "line", "_"];
+
+/// fields parsed from an export file name such as `001_つくよみちゃん（れいせい）_これはテストです。`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParsedName {
+    pub index: Option<u32>,
+    pub speaker: Option<String>,
+    pub style: Option<String>,
+    pub line: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Token(String),
+}
+
+/// pattern of an export file name (without extension), e.g. `{index}_{speaker}（{style}）_{line}`.
+/// # Tokens
+/// * `{index}` - digits.
+/// * `{speaker}` / `{style}` / `{line}` - any text.
+/// * `{_}` - any text, ignored.
+///
+/// トークンは最短一致。最後のトークンは残り全て
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenamePattern {
+    source: String,
+    segments: Vec<Segment>,
+}
+impl FilenamePattern {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let err = |msg: String| Error::InvalidPattern(format!("{} in \"{}\"", msg, source));
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '}' => return Err(err("unmatched '}'".to_string())),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') | None => return Err(err("unclosed '{'".to_string())),
+                            Some(v) => name.push(v),
+                        }
+                    }
+                    let name = name.trim();
+                    if !TOKENS.contains(&name) {
+                        return Err(err(format!("unknown token \"{}\"", name)));
+                    }
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    // 区切りの無い連続したトークンは分けられないため
+                    if matches!(segments.last(), Some(Segment::Token(_))) {
+                        return Err(err("tokens must be separated by text".to_string()));
+                    }
+                    segments.push(Segment::Token(name.to_string()));
+                }
+                _ => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        if !segments.iter().any(|s| matches!(s, Segment::Token(_))) {
+            return Err(err("no token".to_string()));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// VOICEVOX, COEIROINKなど: `001_四国めたん（ノーマル）_セリフ`
+    pub fn voicevox() -> Self {
+        Self::parse("{index}_{speaker}（{style}）_{line}").unwrap()
+    }
+
+    /// 半角括弧の場合: `001_speaker(style)_セリフ`
+    pub fn voicevox_ascii() -> Self {
+        Self::parse("{index}_{speaker}({style})_{line}").unwrap()
+    }
+
+    /// スタイルが無い場合: `001_speaker_セリフ`
+    pub fn index_speaker_line() -> Self {
+        Self::parse("{index}_{speaker}_{line}").unwrap()
+    }
+
+    /// VoiSona Talk: `Talk1_1` (トラック名_番号)
+    pub fn voisona() -> Self {
+        Self::parse("{_}_{index}").unwrap()
+    }
+
+    /// return the fields if `stem` matches the pattern.
+    pub fn parse_stem(&self, stem: &str) -> Option<ParsedName> {
+        let captures = match_segments(&self.segments, stem)?;
+        let mut parsed = ParsedName::default();
+        for (name, value) in captures {
+            let value = value.trim().to_string();
+            match name {
+                "index" => parsed.index = value.parse().ok(),
+                "speaker" => parsed.speaker = Some(value),
+                "style" => parsed.style = Some(value),
+                "line" => parsed.line = Some(value),
+                _ => {}
+            }
+        }
+        Some(parsed)
+    }
+}
+
+/// 最初に一致したパターンの結果を返す
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameParser {
+    patterns: Vec<FilenamePattern>,
+}
+impl Default for FilenameParser {
+    /// 組み込みのパターンで初期化
+    fn default() -> Self {
+        Self {
+            patterns: vec![
+                FilenamePattern::voicevox(),
+                FilenamePattern::voicevox_ascii(),
+                FilenamePattern::index_speaker_line(),
+                FilenamePattern::voisona(),
+            ],
+        }
+    }
+}
+impl FilenameParser {
+    /// パターン無し(何も読み取らない)
+    pub fn empty() -> Self {
+        Self {
+            patterns: Vec::new(),
+        }
+    }
+
+    /// add a user-defined pattern. 組み込みのパターンより先に試す
+    pub fn push(&mut self, pattern: FilenamePattern) {
+        self.patterns.insert(0, pattern);
+    }
+
+    pub fn parse(&self, stem: &str) -> Option<ParsedName> {
+        self.patterns.iter().find_map(|p| p.parse_stem(stem))
+    }
+}
+
+/// `segments`が`s`全体に一致した場合、(トークン名, 値)のリストを返す
+fn match_segments<'a>(segments: &'a [Segment], s: &'a str) -> Option<Vec<(&'a str, &'a str)>> {
+    let (first, rest) = match segments.split_first() {
+        Some(v) => v,
+        None => return s.is_empty().then(Vec::new),
+    };
+    match first {
+        Segment::Text(t) => match_segments(rest, s.strip_prefix(t.as_str())?),
+        Segment::Token(name) => {
+            let valid = |v: &str| {
+                !v.is_empty() && (name != "index" || v.chars().all(|c| c.is_ascii_digit()))
+            };
+            if rest.is_empty() {
+                return valid(s).then(|| vec![(name.as_str(), s)]);
+            }
+            s.char_indices()
+                .skip(1)
+                .map(|(i, _)| i)
+                .filter(|i| valid(&s[..*i]))
+                .find_map(|i| {
+                    let mut captures = match_segments(rest, &s[i..])?;
+                    captures.insert(0, (name.as_str(), &s[..i]));
+                    Some(captures)
+                })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins() {
+        let parser = FilenameParser::default();
+        let parsed = parser
+            .parse("001_つくよみちゃん（れいせい）_これはテストです。")
+            .unwrap();
+        assert_eq!(parsed.index, Some(1));
+        assert_eq!(parsed.speaker.as_deref(), Some("つくよみちゃん"));
+        assert_eq!(parsed.style.as_deref(), Some("れいせい"));
+        assert_eq!(parsed.line.as_deref(), Some("これはテストです。"));
+
+        let parsed = parser.parse("002_speaker_line_with_underscore").unwrap();
+        assert_eq!(parsed.speaker.as_deref(), Some("speaker"));
+        assert_eq!(parsed.line.as_deref(), Some("line_with_underscore"));
+
+        let parsed = parser.parse("Talk1_12").unwrap();
+        assert_eq!(parsed.index, Some(12));
+        assert_eq!(parsed.line, None);
+
+        assert_eq!(parser.parse("non_line"), None);
+    }
+
+    #[test]
+    fn user_defined() {
+        let mut parser = FilenameParser::empty();
+        parser.push(FilenamePattern::parse("{speaker}-{line}-{index}").unwrap());
+        let parsed = parser.parse("ずんだもん-こんにちは-3").unwrap();
+        assert_eq!(parsed.speaker.as_deref(), Some("ずんだもん"));
+        assert_eq!(parsed.line.as_deref(), Some("こんにちは"));
+        assert_eq!(parsed.index, Some(3));
+
+        for p in ["", "{index}{line}", "{foo}", "{line", "a}"] {
+            assert!(matches!(
+                FilenamePattern::parse(p),
+                Err(Error::InvalidPattern(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{output::OutputMode, Error, Operation};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// `rename()`が出力フォルダに書き出すjournalのファイル名
+pub const JOURNAL_FILE_NAME: &str = "track2line_journal.toml";
+
+/// one rename done by `PathSets::rename()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub original: PathBuf,
+    pub renamed: PathBuf,
+    /// 古いjournalには無いため、無い場合は`Move`として扱う
+    #[serde(default)]
+    pub mode: OutputMode,
+}
+
+/// list of (original path, new path) pairs written by `PathSets::rename()`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Journal {
+    pub entries: Vec<JournalEntry>,
+}
+impl Journal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        original: P,
+        renamed: Q,
+        mode: OutputMode,
+    ) {
+        self.entries.push(JournalEntry {
+            original: original.as_ref().to_path_buf(),
+            renamed: renamed.as_ref().to_path_buf(),
+            mode,
+        });
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let content = fs::read_to_string(&path).map_err(Error::io(Operation::Read, &path))?;
+        toml::from_str(&content).map_err(|e| Error::InvalidJournal {
+            path: path.as_ref().to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+
+    /// ファイルを上書きで保存する
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let content = toml::to_string(self).map_err(|e| Error::InvalidJournal {
+            path: path.as_ref().to_path_buf(),
+            source: Box::new(e),
+        })?;
+        fs::write(&path, content).map_err(Error::io(Operation::Write, &path))
+    }
+
+    /// restore every file to the original path.
+    /// `Move`以外では元のファイルは残っているため、出力したファイルを削除する。
+    /// 元のファイルが消されている場合は、出力したファイルを元のパスに戻す
+    /// (シンボリックリンクは戻せないため失敗にする)。
+    /// 元に戻せなかったものは`UndoReport::failed`に入る。
+    /// 既に戻されているものはスキップするため、2回実行しても安全
+    pub fn undo(&self) -> UndoReport {
+        let mut report = UndoReport::default();
+        // 後からrenameしたものから戻す
+        for entry in self.entries.iter().rev() {
+            // シンボリックリンクはリンク先ではなくリンクそのものを見る
+            if fs::symlink_metadata(&entry.renamed).is_err() {
+                if entry.original.exists() {
+                    report.already_restored.push(entry.clone());
+                } else {
+                    report.failed.push((
+                        entry.clone(),
+                        io::Error::new(io::ErrorKind::NotFound, "renamed file not found"),
+                    ));
+                }
+                continue;
+            }
+            if entry.mode != OutputMode::Move && !entry.original.exists() {
+                if entry.mode == OutputMode::Symlink {
+                    report.failed.push((
+                        entry.clone(),
+                        io::Error::new(io::ErrorKind::NotFound, "original file not found"),
+                    ));
+                    continue;
+                }
+                // 残っているのは出力したファイルだけなので、削除せずに戻す
+                match fs::rename(&entry.renamed, &entry.original) {
+                    Ok(_) => report.restored.push(entry.clone()),
+                    Err(e) => report.failed.push((entry.clone(), e)),
+                }
+                continue;
+            }
+            if entry.mode != OutputMode::Move {
+                match fs::remove_file(&entry.renamed) {
+                    Ok(_) => report.restored.push(entry.clone()),
+                    Err(e) => report.failed.push((entry.clone(), e)),
+                }
+                continue;
+            }
+            if entry.original.exists() {
+                // 上書きしないようにする
+                report.failed.push((
+                    entry.clone(),
+                    io::Error::new(io::ErrorKind::AlreadyExists, "original path already exists"),
+                ));
+                continue;
+            }
+            match fs::rename(&entry.renamed, &entry.original) {
+                Ok(_) => report.restored.push(entry.clone()),
+                Err(e) => report.failed.push((entry.clone(), e)),
+            }
+        }
+        report
+    }
+}
+
+/// result of `Journal::undo()`.
+#[derive(Debug, Default)]
+pub struct UndoReport {
+    pub restored: Vec<JournalEntry>,
+    pub already_restored: Vec<JournalEntry>,
+    pub failed: Vec<(JournalEntry, io::Error)>,
+}
+impl UndoReport {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+impl fmt::Display for UndoReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "restored: {}, already restored: {}, failed: {}",
+            self.restored.len(),
+            self.already_restored.len(),
+            self.failed.len()
+        )?;
+        for (entry, e) in &self.failed {
+            writeln!(
+                f,
+                "* {} -x-> {}: {}",
+                entry.renamed.display(),
+                entry.original.display(),
+                e
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{output::OutputMode, tests::ready_in, PathSets};
+    use std::fs;
+
+    #[test]
+    fn undo_twice() {
+        let dir = ready_in("undo_twice");
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.rename().unwrap();
+        assert!(!dir.join("Talk1_1.wav").exists());
+
+        let report = sets.rollback().unwrap();
+        println!("{}", report);
+        assert!(report.is_ok());
+        assert!(!report.restored.is_empty());
+        assert!(dir.join("Talk1_1.wav").exists());
+
+        let second = sets.rollback().unwrap();
+        assert!(second.is_ok());
+        assert!(second.restored.is_empty());
+        assert_eq!(report.restored.len(), second.already_restored.len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn undo_copy() {
+        let dir = ready_in("undo_copy");
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.set_output_mode(OutputMode::Copy);
+        sets.rename().unwrap();
+        assert!(dir.join("Talk1_1.wav").exists());
+
+        let report = sets.rollback().unwrap();
+        assert!(report.is_ok());
+        assert!(dir.join("Talk1_1.wav").exists());
+        assert!(fs::read_dir(dir.join("renamed"))
+            .unwrap()
+            .all(|e| e.unwrap().path() == sets.journal_path()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn undo_copy_source_deleted() {
+        let dir = ready_in("undo_copy_source_deleted");
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.set_output_mode(OutputMode::Copy);
+        sets.rename().unwrap();
+        let bytes = fs::read(dir.join("Talk1_1.wav")).unwrap();
+        fs::remove_file(dir.join("Talk1_1.wav")).unwrap();
+
+        let report = sets.rollback().unwrap();
+        println!("{}", report);
+        assert!(report.is_ok());
+        // 残っていたコピーが元のパスに戻る
+        assert_eq!(fs::read(dir.join("Talk1_1.wav")).unwrap(), bytes);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -4,36 +4,251 @@ mod transcription;
 #[cfg(feature = "config")]
 pub mod config;
 
+pub mod audio;
+pub mod collision;
+pub mod concat;
+pub mod diagnostics;
+mod encoding;
+pub mod exo;
+pub mod filename;
+pub mod journal;
+pub mod nle;
+pub mod order;
+pub mod output;
+pub mod plan;
+pub mod report;
+pub mod sanitize;
+pub mod script;
+pub mod subtitle;
+pub mod template;
+pub mod timeline;
+pub mod truncate;
+pub mod ymm4;
+
+use audio::AudioInfo;
+use collision::{Collision, CollisionPolicy};
+use diagnostics::{Diagnostic, DiagnosticKind};
+use filename::{FilenameParser, ParsedName};
+use journal::{Journal, UndoReport, JOURNAL_FILE_NAME};
+use order::SortOrder;
+use output::{ExistingDirPolicy, OutputMode};
+use plan::{LineSource, LineStatus, Plan, PlanRow};
+use report::{RenameEntry, RenameOutcome, RenameReport};
+use sanitize::Sanitizer;
+use script::{Script, ScriptMismatch};
 use std::{
+    collections::{HashMap, HashSet},
     fmt,
     fs::{self},
     io,
     path::{Path, PathBuf},
+    time::Duration,
 };
+use template::{Fields, Template};
+use timeline::{Clip, Timeline};
+use truncate::Truncation;
+use unicode_width::UnicodeWidthStr;
+
+/// 重複時の接尾辞(`_ffffffff`など)のために空けておくバイト数
+const SUFFIX_RESERVE: usize = 16;
+
+/// what the library was doing when an io error happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// フォルダの中を読む
+    Scan,
+    /// セリフのファイルを読む
+    ReadLine,
+    /// 出力フォルダを作る
+    CreateDir,
+    /// 移動、コピー、リンクで出力する
+    Rename,
+    /// 計画、台本、journalなどを読む
+    Read,
+    /// 計画、journalなどを書き出す
+    Write,
+}
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Operation::Scan => "scan",
+            Operation::ReadLine => "read line file",
+            Operation::CreateDir => "create folder",
+            Operation::Rename => "rename to",
+            Operation::Read => "read",
+            Operation::Write => "write",
+        };
+        write!(f, "{}", op)
+    }
+}
 
 #[derive(Debug)]
 pub enum Error {
-    IoError(io::Error),
+    /// 失敗した操作と対象のパスを持つ
+    Io {
+        op: Operation,
+        path: PathBuf,
+        source: io::Error,
+    },
     ExtensionError,
-    FailedCreateRenamedFolder(io::Error),
     NoParent,
+    /// 読めない、書けないjournal。`source()`でTOMLのエラーを返す
+    InvalidJournal {
+        path: PathBuf,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    Collision(Vec<PathBuf>),
+    InvalidTemplate(String),
+    InvalidPattern(String),
+    /// JSON, CSVに変換できなかった。`path`は保存先(`save_*()`の場合のみ)
+    FailedSerialize {
+        path: Option<PathBuf>,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    InvalidPlan(Vec<String>),
+    UnknownEncoding(String),
+    InvalidScript(String),
+    ScriptMismatch(ScriptMismatch),
+    /// 読めない音声ファイル(パス, 理由)
+    InvalidAudio(PathBuf, String),
+    /// `decode` featureが無いため読めない形式の音声ファイル
+    UnsupportedAudio(PathBuf),
+    /// サンプルレートかチャンネル数が1つ目のクリップと違う音声ファイル
+    MismatchedAudio(Vec<PathBuf>),
+    /// Shift_JISで書けない文字を含む(`.exo`のファイルパスなど)
+    UnmappableText(String),
+    /// 音声認識に失敗した
+    #[cfg(feature = "experimental")]
+    Transcription(String),
+    #[cfg(feature = "config")]
+    Config(config::Error),
+}
+impl Error {
+    /// `map_err(Error::io(Operation::Scan, dir))`のように使う
+    pub(crate) fn io<P: AsRef<Path>>(op: Operation, path: P) -> impl FnOnce(io::Error) -> Self {
+        let path = path.as_ref().to_path_buf();
+        move |source| Error::Io { op, path, source }
+    }
+
+    /// `map_err(Error::serialize)`のように使う
+    pub(crate) fn serialize<E: std::error::Error + Send + Sync + 'static>(source: E) -> Self {
+        Error::FailedSerialize {
+            path: None,
+            source: Box::new(source),
+        }
+    }
+
+    /// `FailedSerialize`に保存先のパスを付ける。それ以外はそのまま
+    pub(crate) fn with_path<P: AsRef<Path>>(self, path: P) -> Self {
+        match self {
+            Error::FailedSerialize { source, .. } => Error::FailedSerialize {
+                path: Some(path.as_ref().to_path_buf()),
+                source,
+            },
+            e => e,
+        }
+    }
+
+    /// the file or folder the error is about, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Io { path, .. }
+            | Error::InvalidJournal { path, .. }
+            | Error::InvalidAudio(path, _)
+            | Error::UnsupportedAudio(path) => Some(path),
+            Error::FailedSerialize { path, .. } => path.as_deref(),
+            _ => None,
+        }
+    }
 }
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::IoError(error) => writeln!(f, "{}", error),
-            Error::ExtensionError => writeln!(f, "extension error"),
-            Error::FailedCreateRenamedFolder(e) => writeln!(f, "failed create renamed folder{}", e),
-            Error::NoParent => writeln!(f, "no parent"),
+            Error::Io { op, path, source } => {
+                write!(f, "failed to {} {}: {}", op, path.display(), source)
+            }
+            Error::ExtensionError => write!(f, "extension error"),
+            Error::NoParent => write!(f, "no parent"),
+            Error::InvalidJournal { path, source } => {
+                write!(f, "invalid journal {}: {}", path.display(), source)
+            }
+            Error::InvalidTemplate(msg) => write!(f, "invalid template: {}", msg),
+            Error::InvalidPattern(msg) => write!(f, "invalid file name pattern: {}", msg),
+            Error::FailedSerialize { path, source } => match path {
+                Some(path) => write!(f, "failed serialize {}: {}", path.display(), source),
+                None => write!(f, "failed serialize: {}", source),
+            },
+            Error::UnknownEncoding(label) => write!(f, "unknown encoding: {}", label),
+            Error::InvalidScript(msg) => write!(f, "invalid script: {}", msg),
+            Error::ScriptMismatch(m) => write!(f, "{}", m),
+            Error::InvalidAudio(path, msg) => {
+                write!(f, "invalid audio file {}: {}", path.display(), msg)
+            }
+            Error::UnsupportedAudio(path) => write!(
+                f,
+                "unsupported audio format {} (only WAV without the `decode` feature)",
+                path.display()
+            ),
+            Error::UnmappableText(text) => {
+                write!(f, "cannot encode in Shift_JIS: {}", text)
+            }
+            Error::MismatchedAudio(list) => write!(
+                f,
+                "sample rate or channels differ from the first clip: {}",
+                list.iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Error::InvalidPlan(problems) => write!(f, "invalid plan: {}", problems.join(", ")),
+            Error::Collision(list) => write!(
+                f,
+                "collision: {}",
+                list.iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            #[cfg(feature = "experimental")]
+            Error::Transcription(msg) => write!(f, "transcription failed: {}", msg),
+            #[cfg(feature = "config")]
+            Error::Config(e) => write!(f, "config: {}", e),
         }
     }
 }
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::InvalidJournal { source, .. } | Error::FailedSerialize { source, .. } => {
+                Some(source.as_ref())
+            }
+            #[cfg(feature = "config")]
+            Error::Config(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+#[cfg(feature = "config")]
+impl From<config::Error> for Error {
+    fn from(e: config::Error) -> Self {
+        Error::Config(e)
+    }
+}
 
 #[derive(Debug)]
 struct PathSet {
     audio_path: PathBuf,
     changed_audio_path: Option<PathBuf>,
     line: String,
+    /// ファイル名から読み取った番号、話者、スタイル、セリフ
+    parsed: ParsedName,
+    /// 一緒に出力する同名のファイル(元のパス, 変更後のパス)
+    sidecars: Vec<(PathBuf, PathBuf)>,
+    status: LineStatus,
+    line_source: LineSource,
+    /// `work_dir`からの相対パス。出力フォルダの下に同じ構成で出力する
+    sub_dir: PathBuf,
 }
 impl PathSet {
     /// init時に変更後の`changed_audio_path`が取得できることはないため引数は以下のみ
@@ -42,6 +257,11 @@ impl PathSet {
             audio_path: audio_path.as_ref().to_path_buf(),
             changed_audio_path: None,
             line: line.as_ref().to_string(),
+            parsed: ParsedName::default(),
+            sidecars: Vec::new(),
+            status: LineStatus::Ok,
+            line_source: LineSource::Placeholder,
+            sub_dir: PathBuf::new(),
         }
     }
 }
@@ -53,31 +273,67 @@ impl fmt::Display for PathSet {
     }
 }
 
-pub struct ListForCheck(Vec<(Option<String>, Option<String>)>);
+/// (元のファイル名, 変更後のファイル名, 一緒に出力するファイルの(元のファイル名, 変更後のファイル名))
+type CheckRow = (Option<String>, Option<String>, Vec<(String, String)>);
+
+pub struct ListForCheck {
+    list: Vec<CheckRow>,
+    collisions: Vec<Collision>,
+    script_mismatch: Option<ScriptMismatch>,
+    truncation: Truncation,
+}
 
 impl ListForCheck {
-    fn new() -> Self {
-        Self(Vec::<(Option<String>, Option<String>)>::new())
+    fn new(truncation: Truncation) -> Self {
+        Self {
+            list: Vec::<CheckRow>::new(),
+            collisions: Vec::new(),
+            script_mismatch: None,
+            truncation,
+        }
+    }
+
+    /// new file names claimed by more than one entry or already existing.
+    pub fn collisions(&self) -> &[Collision] {
+        &self.collisions
+    }
+
+    /// set if the number of the script lines and the audio files differ.
+    pub fn script_mismatch(&self) -> Option<ScriptMismatch> {
+        self.script_mismatch
     }
 }
 
 impl fmt::Display for ListForCheck {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for (old, new) in &self.0 {
-            let oldd = &old
-                .as_ref()
-                .unwrap_or(&"None".to_string())
-                .chars()
-                .take(20)
-                .collect::<String>();
+        let olds = self
+            .list
+            .iter()
+            .map(|(old, _, _)| {
+                self.truncation
+                    .truncate(old.as_ref().unwrap_or(&"None".to_string()))
+            })
+            .collect::<Vec<_>>();
+        // 全角文字は2列分なので、文字数ではなく表示幅で揃える
+        let width = olds.iter().map(|o| o.width()).max().unwrap_or(0).max(20);
 
+        for ((_, new, sidecars), oldd) in self.list.iter().zip(olds) {
             writeln!(
                 f,
-                "* {:width$} ---> {}",
+                "* {}{} ---> {}",
                 oldd,
+                " ".repeat(width - oldd.width()),
                 new.as_ref().unwrap_or(&"None".to_string()),
-                width = 20
             )?;
+            for (old, new) in sidecars {
+                writeln!(f, "  + {} ---> {}", old, new)?;
+            }
+        }
+        for i in &self.collisions {
+            writeln!(f, "{}", i)?;
+        }
+        if let Some(m) = self.script_mismatch {
+            writeln!(f, "! {}", m)?;
         }
         Ok(())
     }
@@ -87,8 +343,38 @@ impl fmt::Display for ListForCheck {
 pub struct PathSets {
     work_dir: PathBuf,
     list: Vec<PathSet>,
-    audio_extension: String,
-    // line_extension: String,
+    audio_extensions: Vec<String>,
+    line_extension: String,
+    /// 拡張子の大文字小文字を区別しない
+    ignore_case: bool,
+    /// セリフのファイルの文字コードが判定できない場合に使う
+    fallback_encoding: &'static encoding_rs::Encoding,
+    filename_parser: FilenameParser,
+    /// セリフを取る順番。どれでも取れない場合は`empty_N`
+    line_sources: Vec<LineSource>,
+    /// `LineSource::Transcription`で使うwhisperのモデル
+    transcription_model: Option<PathBuf>,
+    /// 1つの台本を使う場合。`line_sources`より優先する
+    script: Option<Script>,
+    script_mismatch: Option<ScriptMismatch>,
+    sort_order: SortOrder,
+    /// 読んだ時に見つかった、対応する音声ファイルが無いテキストファイルと隠しファイル
+    scan_diagnostics: Vec<Diagnostic>,
+    /// サブフォルダを何階層まで読むか。0の場合は`work_dir`のみ
+    max_depth: usize,
+    sanitizer: Sanitizer,
+    collision_policy: CollisionPolicy,
+    collisions: Vec<Collision>,
+    template: Template,
+    truncation: Truncation,
+    output_mode: OutputMode,
+    base_output_dir: PathBuf,
+    existing_dir_policy: ExistingDirPolicy,
+    /// `base_output_dir`に`existing_dir_policy`を適用した実際の出力フォルダ。`ready_rename()`で決まる
+    output_dir: PathBuf,
+    sidecar_extensions: Vec<String>,
+    /// `from_plan()`で作られた場合は変更後のパスを計画のまま使う
+    from_plan: bool,
 }
 impl fmt::Display for PathSets {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -109,168 +395,1024 @@ impl PathSets {
         audio_extension: S,
         line_extension: S,
     ) -> Result<Self, Error> {
-        let filtered_path_list =
-            get_file_list(&dir, audio_extension.as_ref(), line_extension.as_ref())?;
-
-        let tmp_list = build_path_sets(
-            filtered_path_list,
-            audio_extension.as_ref(),
+        let mut new = Self::with_list(
+            dir,
+            Vec::new(),
+            vec![audio_extension.as_ref().to_string()],
             line_extension.as_ref(),
-        )?;
+        );
+        new.rescan()?;
+        Ok(new)
+    }
 
-        let mut new = PathSets {
+    /// 設定はデフォルトのまま。changed_audio_pathは設定しない
+    fn with_list<P: AsRef<Path>>(
+        dir: P,
+        list: Vec<PathSet>,
+        audio_extensions: Vec<String>,
+        line_extension: &str,
+    ) -> Self {
+        PathSets {
             work_dir: dir.as_ref().to_path_buf(),
-            list: tmp_list,
-            audio_extension: audio_extension.as_ref().to_string(),
-        };
-        new.ready_rename();
+            list,
+            audio_extensions,
+            line_extension: line_extension.to_string(),
+            ignore_case: false,
+            fallback_encoding: encoding::default_fallback(),
+            filename_parser: FilenameParser::default(),
+            line_sources: vec![LineSource::Text, LineSource::Filename],
+            transcription_model: None,
+            script: None,
+            script_mismatch: None,
+            sort_order: SortOrder::default(),
+            scan_diagnostics: Vec::new(),
+            max_depth: 0,
+            sanitizer: Sanitizer::default(),
+            collision_policy: CollisionPolicy::default(),
+            collisions: Vec::new(),
+            template: Template::default(),
+            truncation: Truncation::default(),
+            output_mode: OutputMode::default(),
+            base_output_dir: dir.as_ref().join("renamed"),
+            existing_dir_policy: ExistingDirPolicy::default(),
+            output_dir: dir.as_ref().join("renamed"),
+            sidecar_extensions: Vec::new(),
+            from_plan: false,
+        }
+    }
+
+    /// create a new instance of PathSets from a rename plan edited by hand.
+    /// 全ての元のファイルが存在し、変更後のファイル名が有効で重複しないことを確認する。
+    /// 問題があった場合は全ての問題を`Error::InvalidPlan`で返す。空の計画もエラーになる。
+    /// 変更後のパスは計画のまま使うため、`set_template()`などの設定は反映されない。
+    /// 相対パスの変更後のパスは元のファイルのフォルダからのパスとして扱う
+    pub fn from_plan(plan: &Plan) -> Result<Self, Error> {
+        if plan.rows.is_empty() {
+            return Err(Error::InvalidPlan(vec!["empty plan".to_string()]));
+        }
+        let mut problems = Vec::<String>::new();
+        let mut taken = HashSet::<String>::new();
+        let sanitizer = Sanitizer::default();
+        let targets = plan
+            .rows
+            .iter()
+            .map(|r| {
+                r.target.as_ref().map(|t| match r.source.parent() {
+                    Some(parent) if t.is_relative() => parent.join(t),
+                    _ => t.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for (row, target) in plan.rows.iter().zip(&targets) {
+            if !row.source.is_file() {
+                problems.push(format!("source not found: {}", row.source.display()));
+            }
+            let target = match target {
+                Some(v) => v,
+                None => continue,
+            };
+            let name = target
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if name.is_empty()
+                || sanitizer.sanitize(&name) != name
+                || name.len() > truncate::MAX_FILE_NAME_BYTES
+            {
+                problems.push(format!("invalid target name: {}", target.display()));
+            }
+            if !taken.insert(collision::key(&target.to_string_lossy())) {
+                problems.push(format!("duplicate target: {}", target.display()));
+            } else if fs::symlink_metadata(target).is_ok() {
+                problems.push(format!("target already exists: {}", target.display()));
+            }
+        }
+        if !problems.is_empty() {
+            return Err(Error::InvalidPlan(problems));
+        }
+
+        let first = plan.rows.first();
+        let work_dir = first
+            .and_then(|r| r.source.parent())
+            .map(|p| p.to_path_buf())
+            .unwrap_or_default();
+        let list = plan
+            .rows
+            .iter()
+            .zip(&targets)
+            .map(|(r, target)| {
+                let mut set = PathSet::new(&r.source, &r.line);
+                set.changed_audio_path = target.clone();
+                set.status = r.status;
+                set.line_source = r.line_source;
+                set
+            })
+            .collect();
+
+        let mut new = Self::with_list(&work_dir, list, Vec::new(), "");
+        new.from_plan = true;
+        // 出力フォルダは既にある場合もあるため
+        new.existing_dir_policy = ExistingDirPolicy::Merge;
+        if let Some(dir) = targets
+            .iter()
+            .find_map(|t| t.as_ref().and_then(|t| t.parent()))
+        {
+            new.base_output_dir = dir.to_path_buf();
+            new.output_dir = dir.to_path_buf();
+        }
         Ok(new)
     }
 
     /// この関数はまだ正常に動作しません
     #[cfg(feature = "experimental")]
-    pub fn new_transcription<P: AsRef<Path>, S: AsRef<str>>(
+    pub fn new_transcription<P: AsRef<Path>, S: AsRef<str>, M: AsRef<Path>>(
         dir: P,
         audio_extension: S,
         line_extension: S,
+        model_path: M,
     ) -> Result<Self, Error> {
-        let filtered_path_list =
-            get_file_list(&dir, audio_extension.as_ref(), line_extension.as_ref())?;
+        let audio = [audio_extension.as_ref().to_string()];
+        let filename_parser = FilenameParser::default();
+        let matcher = ExtMatcher {
+            audio: &audio,
+            line: line_extension.as_ref(),
+            ignore_case: false,
+            fallback_encoding: encoding::default_fallback(),
+            filename_parser: &filename_parser,
+            line_sources: &[LineSource::Transcription],
+            transcription_model: Some(model_path.as_ref()),
+        };
+        let filtered_path_list = get_file_list(&dir, &matcher, 0, &[])?;
 
         let path_set_list = filtered_path_list
             .iter()
-            .filter(|f| f.extension().unwrap().to_str() == Some(audio_extension.as_ref()))
+            .filter(|f| matcher.is_audio(f))
             .map(|audio_path| {
-                let line = transcription::transcription("model_path", audio_path, Some("ja"));
-                PathSet::new(audio_path, line)
+                let line = transcription::transcription(&model_path, audio_path, Some("ja"))?;
+                Ok(PathSet::new(audio_path, line))
             })
-            .collect();
+            .collect::<Result<_, Error>>()?;
 
-        let mut new = PathSets {
-            work_dir: dir.as_ref().to_path_buf(),
-            list: path_set_list,
-            audio_extension: audio_extension.as_ref().to_string(),
-        };
+        let mut new = Self::with_list(
+            dir,
+            path_set_list,
+            vec![audio_extension.as_ref().to_string()],
+            line_extension.as_ref(),
+        );
         new.ready_rename();
         Ok(new)
     }
 
+    /// set how deep to scan the subfolders of `work_dir` and rescan.
+    /// 0の場合は`work_dir`のみ(デフォルト)、`usize::MAX`の場合は制限なし。
+    /// サブフォルダの構成は出力フォルダの下にそのまま作られる。
+    /// 出力フォルダと、以前の出力フォルダ(journalがあるフォルダ)は読まない
+    pub fn set_max_depth(&mut self, depth: usize) -> Result<(), Error> {
+        self.max_depth = depth;
+        self.rescan()
+    }
+
+    /// set the extensions of the audio files and rescan. e.g. `["wav", "mp3", "ogg"]`.
+    /// 変更後のファイルはそれぞれ元の拡張子のまま
+    pub fn set_audio_extensions(&mut self, extensions: &[&str]) -> Result<(), Error> {
+        self.audio_extensions = extensions.iter().map(|e| e.to_string()).collect();
+        self.rescan()
+    }
+
+    /// set whether to ignore the case of the extensions (`Talk1_1.WAV`) and rescan.
+    pub fn set_ignore_case(&mut self, ignore_case: bool) -> Result<(), Error> {
+        self.ignore_case = ignore_case;
+        self.rescan()
+    }
+
+    /// set the encoding used when the encoding of a line file can't be detected, and rescan.
+    /// e.g. `"shift_jis"`(デフォルト), `"euc-jp"`, `"utf-16le"`.
+    /// BOMがある場合とUTF-8の場合はそちらを優先する
+    pub fn set_fallback_encoding(&mut self, label: &str) -> Result<(), Error> {
+        self.fallback_encoding =
+            encoding::for_label(label).ok_or(Error::UnknownEncoding(label.to_string()))?;
+        self.rescan()
+    }
+
+    /// set the parser for the file names of the audio files and rescan.
+    /// 読み取った話者とスタイルはテンプレートで使え、テキストファイルが無い場合はセリフとして使う。
+    /// デフォルトはVOICEVOXとVoiSonaなどの組み込みのパターン。`FilenameParser::empty()`で無効にできる
+    pub fn set_filename_parser(&mut self, parser: FilenameParser) -> Result<(), Error> {
+        self.filename_parser = parser;
+        self.rescan()
+    }
+
+    /// set the order of the sources to take the lines from and rescan.
+    /// デフォルトはテキストファイル、ファイル名の順。
+    /// `LineSource::Transcription`は`experimental` featureと`set_transcription_model()`が必要。
+    /// どれでも取れない場合は`empty_N`(`LineSource::Placeholder`)になる。
+    /// 使われたものは`plan()`の`line_source`で確認できる
+    pub fn set_line_sources(&mut self, sources: &[LineSource]) -> Result<(), Error> {
+        self.line_sources = sources.to_vec();
+        self.rescan()
+    }
+
+    /// set the whisper model used by `LineSource::Transcription` and rescan. `None`で解除する
+    pub fn set_transcription_model<P: AsRef<Path>>(
+        &mut self,
+        model: Option<P>,
+    ) -> Result<(), Error> {
+        self.transcription_model = model.map(|m| m.as_ref().to_path_buf());
+        self.rescan()
+    }
+
+    /// set one script file for all audio files and rescan. `None`で解除する。
+    /// N番目のセリフを、自然順(`Talk1_2`が`Talk1_10`より前)でN番目の音声ファイルに対応させる。
+    /// 台本に話者がある場合は`{speaker}`に使う。
+    /// セリフと音声ファイルの数が違う場合は`check()`に表示し、`rename()`はエラーにする
+    pub fn set_script(&mut self, script: Option<Script>) -> Result<(), Error> {
+        self.script = script;
+        self.rescan()
+    }
+
+    /// set the order of the entries and rescan. デフォルトは自然順。
+    /// `{index}`と`empty_N`の番号、`check()`の表示順はこの順になる
+    pub fn set_sort_order(&mut self, order: SortOrder) -> Result<(), Error> {
+        self.sort_order = order;
+        self.rescan()
+    }
+
+    /// 設定を元にファイルを読み直し、changed_audio_pathを作り直す
+    fn rescan(&mut self) -> Result<(), Error> {
+        if self.from_plan {
+            return Ok(());
+        }
+        let skip = [self.base_output_dir.clone(), self.output_dir.clone()];
+        let matcher = ExtMatcher {
+            audio: &self.audio_extensions,
+            line: &self.line_extension,
+            ignore_case: self.ignore_case,
+            fallback_encoding: self.fallback_encoding,
+            filename_parser: &self.filename_parser,
+            line_sources: &self.line_sources,
+            transcription_model: self.transcription_model.as_deref(),
+        };
+        let mut filtered_path_list =
+            get_file_list(&self.work_dir, &matcher, self.max_depth, &skip)?;
+        order::sort(
+            &mut filtered_path_list,
+            self.sort_order,
+            &self.work_dir,
+            &self.filename_parser,
+        );
+        self.scan_diagnostics = scan_diagnostics(&filtered_path_list, &matcher);
+
+        let mut tmp_list = build_path_sets(filtered_path_list, &matcher)?;
+        for i in &mut tmp_list {
+            i.sub_dir = i
+                .audio_path
+                .parent()
+                .and_then(|p| p.strip_prefix(&self.work_dir).ok())
+                .map(|p| p.to_path_buf())
+                .unwrap_or_default();
+        }
+        self.script_mismatch = None;
+        if let Some(script) = &self.script {
+            tmp_list.sort_by(|a, b| {
+                let relative = |p: &Path| {
+                    p.strip_prefix(&self.work_dir)
+                        .unwrap_or(p)
+                        .to_string_lossy()
+                        .to_string()
+                };
+                order::natural_cmp(&relative(&a.audio_path), &relative(&b.audio_path))
+            });
+            for (i, l) in tmp_list.iter_mut().zip(&script.lines) {
+                i.line = l.line.clone();
+                i.status = LineStatus::Ok;
+                i.line_source = LineSource::Script;
+                if l.speaker.is_some() {
+                    i.parsed.speaker = l.speaker.clone();
+                }
+            }
+            if script.lines.len() != tmp_list.len() {
+                self.script_mismatch = Some(ScriptMismatch {
+                    lines: script.lines.len(),
+                    audio: tmp_list.len(),
+                });
+            }
+        }
+        self.list = tmp_list;
+        self.ready_rename();
+        Ok(())
+    }
+
+    /// set the sanitizer used for the new file names and rebuild them.
+    pub fn set_sanitizer(&mut self, sanitizer: Sanitizer) {
+        self.sanitizer = sanitizer;
+        self.ready_rename();
+    }
+
+    /// set the naming template for the new file names and rebuild them.
+    /// see `Template` for the tokens.
+    pub fn set_template(&mut self, template: &str) -> Result<(), Error> {
+        self.template = Template::parse(template)?;
+        self.ready_rename();
+        Ok(())
+    }
+
+    /// set how to cut lines for the new file names and the preview of `check()`, and rebuild them.
+    pub fn set_truncation(&mut self, truncation: Truncation) {
+        self.truncation = truncation;
+        self.ready_rename();
+    }
+
+    /// set how `rename()` puts the audio files into the output folder.
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        self.output_mode = mode;
+    }
+
+    /// set the folder to output to, rescan and rebuild the new paths.
+    /// `work_dir`の外も指定可能。デフォルトは`<work_dir>/renamed`。
+    /// 出力フォルダの中のファイルはスキャンしない
+    pub fn set_output_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), Error> {
+        self.base_output_dir = dir.as_ref().to_path_buf();
+        self.output_dir = output::resolve_dir(&self.base_output_dir, self.existing_dir_policy);
+        self.rescan()
+    }
+
+    /// set what to do when the output folder already exists, rescan and rebuild the new paths.
+    pub fn set_existing_dir_policy(&mut self, policy: ExistingDirPolicy) -> Result<(), Error> {
+        self.existing_dir_policy = policy;
+        self.output_dir = output::resolve_dir(&self.base_output_dir, self.existing_dir_policy);
+        self.rescan()
+    }
+
+    /// the folder where the audio and line files are located.
+    pub fn work_dir(&self) -> &Path {
+        &self.work_dir
+    }
+
+    /// the folder `rename()` outputs to.
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    /// set the extensions of the files carried to the output folder with the audio file under the new name.
+    /// e.g. `["txt", "lab", "json"]`. セリフのファイルも一緒に出力する場合はその拡張子も含める。
+    /// 空の場合は音声ファイルのみ(デフォルト)。
+    /// 一緒に出力するファイルの名前も重複の確認と`CollisionPolicy`の対象になる。
+    /// `a.wav`と`a.mp3`のように同じ名前の音声ファイルがある場合は、最初のものと一緒に出力する
+    pub fn set_sidecars(&mut self, extensions: &[&str]) {
+        self.sidecar_extensions = extensions.iter().map(|e| e.to_string()).collect();
+        self.ready_rename();
+    }
+
+    /// set how to resolve entries that have the same new file name and rebuild them.
+    pub fn set_collision_policy(&mut self, policy: CollisionPolicy) {
+        self.collision_policy = policy;
+        self.ready_rename();
+    }
+
     /// self.lineの内容を元にchanged_audio_pathをSome(path)に書き換え
     fn ready_rename(&mut self) {
-        for i in &mut self.list {
+        if self.from_plan {
+            return;
+        }
+        self.output_dir = output::resolve_dir(&self.base_output_dir, self.existing_dir_policy);
+        let renamed_dir = self.output_dir.clone();
+
+        // 出力フォルダに既にあるファイルも重複として扱う。keyは出力フォルダからの相対パス
+        let existing = self
+            .list
+            .iter()
+            .map(|i| &i.sub_dir)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .flat_map(|sub_dir| {
+                fs::read_dir(renamed_dir.join(sub_dir))
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|e| e.ok())
+                    .map(|e| collision::key(&sub_dir.join(e.file_name()).to_string_lossy()))
+            })
+            .collect::<HashSet<_>>();
+        let mut taken = existing.clone();
+        // key -> その名前を最初に使ったself.listのindex
+        let mut first_claims = HashMap::<String, usize>::new();
+        // key -> collisionsのindex
+        let mut claims = HashMap::<String, usize>::new();
+        let mut collisions = Vec::<Collision>::new();
+        // (音声ファイルの名前, 一緒に出力するファイルの(元のパス, 新しいパス))
+        let mut planned = Vec::<(String, Vec<(PathBuf, PathBuf)>)>::new();
+        // 同じ名前の音声ファイルが複数ある場合(`a.wav`, `a.mp3`)、一緒に出力するファイルは最初のものだけ
+        let mut claimed_sidecars = HashSet::<PathBuf>::new();
+        let date = template::today();
+        let matcher = ExtMatcher {
+            audio: &self.audio_extensions,
+            line: &self.line_extension,
+            ignore_case: self.ignore_case,
+            fallback_encoding: self.fallback_encoding,
+            filename_parser: &self.filename_parser,
+            line_sources: &self.line_sources,
+            transcription_model: self.transcription_model.as_deref(),
+        };
+
+        for (index, i) in self.list.iter().enumerate() {
+            // 変更後も元の拡張子のまま
+            let ext = i
+                .audio_path
+                .extension()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default();
             //build_path_sets()にてセリフが空の処理はしてあるためここでは不要
-            i.changed_audio_path = Some(
-                self.work_dir
-                    .join("renamed")
-                    .join(&i.line)
-                    .with_extension(&self.audio_extension),
+            let rendered = self.template.render(&Fields {
+                stem: &i
+                    .audio_path
+                    .file_stem()
+                    .map(|f| f.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                index: index + 1,
+                line: &self.truncation.truncate(&i.line),
+                number: i.parsed.index.map(|n| n as usize).unwrap_or(index + 1),
+                speaker: i.parsed.speaker.as_deref(),
+                style: i.parsed.style.as_deref(),
+                ext: &ext,
+                date: &date,
+            });
+            // 255バイトを超えないように、拡張子と重複時の接尾辞の分を空けておく
+            let stem = truncate::limit_bytes(
+                &self.sanitizer.sanitize(&rendered),
+                truncate::MAX_FILE_NAME_BYTES - ext.len() - 1 - SUFFIX_RESERVE,
             );
+            let sidecars = self
+                .sidecar_extensions
+                .iter()
+                .filter_map(|ext| find_sidecar(&i.audio_path, ext, &matcher))
+                .filter(|p| !claimed_sidecars.contains(p))
+                .collect::<Vec<_>>();
+            // 拡張子は元のファイルのまま
+            let sidecar_exts = sidecars
+                .iter()
+                .map(|p| {
+                    p.extension()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect::<Vec<_>>();
+            // セリフに"."が含まれる場合があるためwith_extension()は使わない
+            // 一緒に出力するファイルの名前も重複を確認する
+            let names_of = |stem: &str| {
+                [&ext]
+                    .into_iter()
+                    .chain(&sidecar_exts)
+                    .map(|e| format!("{}.{}", stem, e))
+                    .collect::<Vec<_>>()
+            };
+            let key_of = |name: &str| collision::key(&i.sub_dir.join(name).to_string_lossy());
+            let file_name = format!("{}.{}", stem, ext);
+            let clash = names_of(&stem)
+                .into_iter()
+                .find(|n| taken.contains(&key_of(n)));
+
+            let file_name = if let Some(clash) = clash {
+                let key = key_of(&clash);
+                match claims.get(&key) {
+                    Some(c) => collisions[*c].sources.push(i.audio_path.clone()),
+                    None => {
+                        claims.insert(key.clone(), collisions.len());
+                        let sources = first_claims
+                            .get(&key)
+                            .map(|f| self.list[*f].audio_path.clone())
+                            .into_iter()
+                            .chain([i.audio_path.clone()])
+                            .collect();
+                        collisions.push(Collision {
+                            target: renamed_dir.join(&i.sub_dir).join(&clash),
+                            sources,
+                            existing: existing.contains(&key),
+                            resolved: self.collision_policy != CollisionPolicy::Abort,
+                        });
+                    }
+                }
+                collision::resolve(
+                    self.collision_policy,
+                    &stem,
+                    &ext,
+                    index + 1,
+                    &i.audio_path,
+                    |name| {
+                        let stem = name.strip_suffix(&format!(".{}", ext)).unwrap_or(name);
+                        names_of(stem).iter().any(|n| taken.contains(&key_of(n)))
+                    },
+                )
+            } else {
+                file_name
+            };
+            let stem = file_name
+                .strip_suffix(&format!(".{}", ext))
+                .unwrap_or(&file_name);
+            for name in names_of(stem) {
+                let key = key_of(&name);
+                first_claims.entry(key.clone()).or_insert(index);
+                taken.insert(key);
+            }
+            let dir = renamed_dir.join(&i.sub_dir);
+            let sidecars = sidecars
+                .into_iter()
+                .zip(&sidecar_exts)
+                .map(|(path, e)| (path, dir.join(format!("{}.{}", stem, e))))
+                .collect::<Vec<_>>();
+            claimed_sidecars.extend(sidecars.iter().map(|(p, _)| p.clone()));
+            planned.push((file_name, sidecars));
         }
+
+        for (i, (file_name, sidecars)) in self.list.iter_mut().zip(planned) {
+            i.changed_audio_path = Some(renamed_dir.join(&i.sub_dir).join(&file_name));
+            i.sidecars = sidecars;
+        }
+        self.collisions = collisions;
     }
 
     /// return list of path to be changed(not renamed yet)
     pub fn check(&self) -> Result<ListForCheck, Error> {
-        let mut list_for_check = ListForCheck::new();
+        let mut list_for_check = ListForCheck::new(self.truncation.clone());
         for i in &self.list {
-            list_for_check.0.push((
+            list_for_check.list.push((
                 i.audio_path
                     .file_name()
                     .map(|f| f.to_string_lossy().to_string()),
                 i.changed_audio_path
                     .as_ref()
                     .and_then(|f| f.file_name().map(|f| f.to_string_lossy().to_string())),
+                i.sidecars
+                    .iter()
+                    .map(|(old, new)| (file_name_of(old), file_name_of(new)))
+                    .collect(),
             ));
         }
+        list_for_check.collisions = self.collisions.clone();
+        list_for_check.script_mismatch = self.script_mismatch;
         Ok(list_for_check)
     }
 
-    /// rename audio files
-    pub fn rename(&mut self) -> Result<(), Error> {
-        create_renamed_folder(&self.work_dir)?;
+    /// check the scanned files and the new file names, and return the problems.
+    /// テキストファイルだけのもの、セリフが無いもの、空のもの、ファイル名の前後の空白、
+    /// 隠しファイル、読めない・途中で切れている音声ファイル、変更後のファイル名の重複、切られる長さのセリフを返す。
+    /// `rename()`の前に書き出し元で直せるようにするためのもの
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.scan_diagnostics.clone();
+        for i in &self.list {
+            let mut push = |kind| {
+                diagnostics.push(Diagnostic {
+                    path: i.audio_path.clone(),
+                    kind,
+                })
+            };
+            let stem = i
+                .audio_path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if stem.trim() != stem || stem.trim().is_empty() {
+                push(DiagnosticKind::WhitespaceStem);
+            }
+            match i.status {
+                LineStatus::Missing => push(DiagnosticKind::MissingText),
+                LineStatus::Empty => push(DiagnosticKind::EmptyText),
+                LineStatus::Ok => {
+                    let length = self.truncation.measure(&i.line);
+                    if length > self.truncation.length() {
+                        push(DiagnosticKind::LongLine {
+                            length,
+                            limit: self.truncation.length(),
+                        });
+                    }
+                }
+            }
+        }
+        for (path, info) in self.audio_info() {
+            // 理由だけを入れる(パスは`Diagnostic::path`にある)
+            let kind = match info {
+                Ok(_) => continue,
+                Err(Error::UnsupportedAudio(_)) => DiagnosticKind::UnsupportedAudio,
+                Err(Error::InvalidAudio(_, reason)) => DiagnosticKind::UnreadableAudio(reason),
+                Err(Error::Io { source, .. }) => {
+                    DiagnosticKind::UnreadableAudio(source.to_string())
+                }
+                Err(e) => DiagnosticKind::UnreadableAudio(e.to_string()),
+            };
+            diagnostics.push(Diagnostic { path, kind });
+        }
+        for c in &self.collisions {
+            for source in &c.sources {
+                diagnostics.push(Diagnostic {
+                    path: source.clone(),
+                    kind: DiagnosticKind::DuplicateTarget {
+                        target: c.target.clone(),
+                        resolved: c.resolved,
+                    },
+                });
+            }
+        }
+        diagnostics
+    }
+
+    /// read the format (length, sample rate, channels, bit depth) of every audio file.
+    /// 読めないファイルは`Err`になる。`validate()`でも報告する
+    pub fn audio_info(&self) -> Vec<(PathBuf, Result<AudioInfo, Error>)> {
+        self.list
+            .iter()
+            .map(|i| (i.audio_path.clone(), audio::probe(&i.audio_path)))
+            .collect()
+    }
+
+    /// lay the audio files out in order with `gap` between them, for subtitles.
+    /// 長さはWAVファイルのヘッダから読む。rename後は変更後のパスから読む
+    pub fn timeline(&self, gap: Duration) -> Result<Timeline, Error> {
+        let mut timeline = Timeline::default();
+        let mut start = Duration::ZERO;
+        for i in &self.list {
+            let path = match &i.changed_audio_path {
+                Some(p) if !i.audio_path.exists() && p.exists() => p.clone(),
+                _ => i.audio_path.clone(),
+            };
+            let duration = audio::probe(&path)?.duration;
+            timeline.clips.push(Clip {
+                source: i.audio_path.clone(),
+                path: i.changed_audio_path.clone().unwrap_or(path),
+                start,
+                duration,
+                line: match i.status {
+                    LineStatus::Ok => i.line.clone(),
+                    _ => String::new(),
+                },
+                speaker: i.parsed.speaker.clone(),
+            });
+            start += duration + gap;
+        }
+        Ok(timeline)
+    }
+
+    /// join the audio files in order into one WAV `output`, with `gap` of silence between them.
+    /// 同じ場所に`<stem>.cue`とAudacityのラベル(`<stem>.labels.txt`)も書き出す。
+    /// ラベルはセリフのファイルとして読まれないように`.labels`を付ける。
+    /// 形式の違うファイルが混ざっている場合は`Error::MismatchedAudio`
+    pub fn concat<P: AsRef<Path>>(&self, output: P, gap: Duration) -> Result<Timeline, Error> {
+        let output = output.as_ref();
+        let joined = concat::concat_wav(&self.timeline(gap)?, output)?;
+        concat::save_cue(&joined, output, output.with_extension("cue"))?;
+        concat::save_labels(&joined, output.with_extension("labels.txt"))?;
+        Ok(joined)
+    }
+
+    /// return the rename plan, which can be exported as JSON or CSV.
+    pub fn plan(&self) -> Plan {
+        Plan {
+            rows: self
+                .list
+                .iter()
+                .map(|i| PlanRow {
+                    source: i.audio_path.clone(),
+                    target: i.changed_audio_path.clone(),
+                    line: match i.status {
+                        LineStatus::Ok => i.line.clone(),
+                        _ => String::new(),
+                    },
+                    status: i.status,
+                    line_source: i.line_source,
+                })
+                .collect(),
+        }
+    }
+
+    /// rename audio files.
+    /// 移動、コピー、リンクのどれで出力するかは`set_output_mode()`で指定する。
+    /// renameした(元のパス, 新しいパス)のjournalを出力フォルダに書き出す。
+    /// journalは移動する前と各ファイルの後に保存し、途中で保存できなかった場合は
+    /// `RenameReport::journal_error`に入れて続ける
+    /// `CollisionPolicy::Abort`で重複が残っている場合は何もせずにエラーを返す
+    /// 各ファイルの結果は`RenameReport`で返す。変更後のパスが1つも無い場合は出力フォルダもjournalも作らない
+    /// 台本のセリフと音声ファイルの数が違う場合も何もせずにエラーを返す
+    pub fn rename(&mut self) -> Result<RenameReport, Error> {
+        if let Some(m) = self.script_mismatch {
+            return Err(Error::ScriptMismatch(m));
+        }
+        let unresolved = self
+            .collisions
+            .iter()
+            .filter(|c| !c.resolved)
+            .map(|c| c.target.clone())
+            .collect::<Vec<_>>();
+        if !unresolved.is_empty() {
+            return Err(Error::Collision(unresolved));
+        }
+        // 出力するものが無い場合はフォルダもjournalも作らない
+        if self.list.iter().all(|i| i.changed_audio_path.is_none()) {
+            let mut report = RenameReport::default();
+            for i in &self.list {
+                report.push(RenameEntry::new(
+                    &i.audio_path,
+                    None::<PathBuf>,
+                    RenameOutcome::Skipped,
+                ));
+            }
+            return Ok(report);
+        }
+        create_renamed_folder(&self.output_dir, self.existing_dir_policy)?;
+        // 既存のフォルダに出力する場合は前回のjournalに追記する
+        let mut journal = match self.journal_path().exists() {
+            true => Journal::load(self.journal_path())?,
+            false => Journal::new(),
+        };
+        // 途中で止まっても戻せるように、移動する前と各エントリの後に保存する
+        let journal_path = self.journal_path();
+        journal.save(&journal_path)?;
+        let mut report = RenameReport::default();
         for i in &mut self.list {
             let changed_audio = match i.changed_audio_path.as_ref() {
-                Some(v) => v,
-                None => continue,
-            };
-            if fs::rename(&i.audio_path, changed_audio).is_err() {
-                i.changed_audio_path = None
+                Some(v) => v.clone(),
+                None => {
+                    report.push(RenameEntry::new(
+                        &i.audio_path,
+                        None::<PathBuf>,
+                        RenameOutcome::Skipped,
+                    ));
+                    continue;
+                }
             };
+            let outcome = put_file(
+                self.output_mode,
+                &i.audio_path,
+                &changed_audio,
+                &mut journal,
+            );
+            let mut entry = RenameEntry::new(&i.audio_path, Some(&changed_audio), outcome);
+            if matches!(entry.outcome, RenameOutcome::Renamed) {
+                for (from, to) in &i.sidecars {
+                    let outcome = put_file(self.output_mode, from, to, &mut journal);
+                    entry
+                        .sidecars
+                        .push(RenameEntry::new(from, Some(to), outcome));
+                }
+            } else {
+                i.changed_audio_path = None;
+            }
+            report.push(entry);
+            if let Err(e) = journal.save(&journal_path) {
+                report.journal_error.get_or_insert(e);
+            }
         }
-        Ok(())
+        Ok(report)
+    }
+
+    /// path of the journal written by `rename()`.
+    pub fn journal_path(&self) -> PathBuf {
+        self.output_dir.join(JOURNAL_FILE_NAME)
+    }
+
+    /// restore every file recorded in the journal to the original path.
+    pub fn undo(journal: &Journal) -> UndoReport {
+        journal.undo()
+    }
+
+    /// load the journal written by `rename()` and restore every file.
+    pub fn rollback(&self) -> Result<UndoReport, Error> {
+        Ok(Self::undo(&Journal::load(self.journal_path())?))
+    }
+}
+
+/// 出力先に既にファイルがある場合は上書きせず`Collided`を返す
+fn put_file(mode: OutputMode, from: &Path, to: &Path, journal: &mut Journal) -> RenameOutcome {
+    if fs::symlink_metadata(to).is_ok() {
+        return RenameOutcome::Collided;
+    }
+    // 計画によっては出力フォルダの外に出力する場合があるため
+    if let Some(parent) = to.parent().filter(|p| !p.exists()) {
+        if let Err(e) = fs::create_dir_all(parent) {
+            return RenameOutcome::Failed(Error::io(Operation::CreateDir, parent)(e));
+        }
+    }
+    match output::apply(mode, from, to) {
+        Ok(_) => {
+            journal.push(from, to, mode);
+            RenameOutcome::Renamed
+        }
+        Err(e) => RenameOutcome::Failed(Error::io(Operation::Rename, to)(e)),
+    }
+}
+
+fn file_name_of(path: &Path) -> String {
+    path.file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// 拡張子が音声ファイルかセリフのファイルのものか判定する
+/// セリフのファイルの文字コード、ファイル名のパターン、セリフを取る順番の設定も持つ
+struct ExtMatcher<'a> {
+    audio: &'a [String],
+    line: &'a str,
+    ignore_case: bool,
+    fallback_encoding: &'static encoding_rs::Encoding,
+    filename_parser: &'a FilenameParser,
+    line_sources: &'a [LineSource],
+    transcription_model: Option<&'a Path>,
+}
+impl ExtMatcher<'_> {
+    fn eq(&self, path: &Path, ext: &str) -> bool {
+        path.extension().is_some_and(|e| match self.ignore_case {
+            true => e.to_string_lossy().eq_ignore_ascii_case(ext),
+            false => e == ext,
+        })
     }
+
+    fn is_audio(&self, path: &Path) -> bool {
+        self.audio.iter().any(|ext| self.eq(path, ext))
+    }
+
+    fn is_line(&self, path: &Path) -> bool {
+        self.eq(path, self.line)
+    }
+
+    /// `.wav`のように拡張子のみの名前(隠しファイル)か
+    fn is_bare(&self, path: &Path) -> bool {
+        let name = file_name_of(path);
+        match name.strip_prefix('.') {
+            Some(ext) => {
+                self.audio
+                    .iter()
+                    .chain([&self.line.to_string()])
+                    .any(|e| match self.ignore_case {
+                        true => e.eq_ignore_ascii_case(ext),
+                        false => e == ext,
+                    })
+            }
+            None => false,
+        }
+    }
+}
+
+/// 音声認識。モデルが指定されていない場合は`None`
+#[cfg(feature = "experimental")]
+fn transcribe(model: Option<&Path>, path: &Path) -> Result<Option<String>, Error> {
+    model
+        .map(|m| transcription::transcription(m, path, Some("ja")))
+        .transpose()
+}
+
+/// `experimental` featureが無い場合は使わない
+#[cfg(not(feature = "experimental"))]
+fn transcribe(_model: Option<&Path>, _path: &Path) -> Result<Option<String>, Error> {
+    Ok(None)
+}
+
+/// `audio`と同じ名前で拡張子が`ext`のファイル。`ignore_case`の場合は大文字小文字を区別しない
+fn find_sidecar(audio: &Path, ext: &str, matcher: &ExtMatcher) -> Option<PathBuf> {
+    let exact = audio.with_extension(ext);
+    if exact.is_file() {
+        return Some(exact);
+    }
+    if !matcher.ignore_case {
+        return None;
+    }
+    let stem = audio.file_stem()?;
+    fs::read_dir(audio.parent()?)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.file_stem() == Some(stem) && matcher.eq(p, ext) && p.is_file())
+}
+
+/// 対応する音声ファイルが無いテキストファイルと、隠しファイルを探す
+fn scan_diagnostics(list: &[PathBuf], matcher: &ExtMatcher) -> Vec<Diagnostic> {
+    let audio_stems = list
+        .iter()
+        .filter(|p| matcher.is_audio(p))
+        .map(|p| p.with_extension(""))
+        .collect::<HashSet<_>>();
+    let mut diagnostics = Vec::new();
+    for path in list {
+        if file_name_of(path).starts_with('.') {
+            diagnostics.push(Diagnostic {
+                path: path.clone(),
+                kind: DiagnosticKind::HiddenFile,
+            });
+        } else if matcher.is_line(path) && !audio_stems.contains(&path.with_extension("")) {
+            diagnostics.push(Diagnostic {
+                path: path.clone(),
+                kind: DiagnosticKind::OrphanText,
+            });
+        }
+    }
+    diagnostics
 }
 
 /// Get file list
-/// audio_extentionかline_extentionにかかるファイルのみのリスト
+/// audio_extentionかline_extentionにかかるファイルのみのリスト。`.wav`のような拡張子のみのファイルも含む。
+/// `depth`が1以上の場合はサブフォルダも読む。`skip`とjournalがあるフォルダ(以前の出力フォルダ)は読まない
 fn get_file_list<P: AsRef<Path>>(
     dir: P,
-    audio_ext: &str,
-    line_ext: &str,
+    matcher: &ExtMatcher,
+    depth: usize,
+    skip: &[PathBuf],
 ) -> Result<Vec<PathBuf>, Error> {
-    Ok(fs::read_dir(&dir)
-        .map_err(Error::IoError)?
+    let mut list = Vec::new();
+    for ok_entry in fs::read_dir(&dir)
+        .map_err(Error::io(Operation::Scan, &dir))?
         .filter_map(|entry| entry.ok())
-        .map(|ok_entry| ok_entry.path())
-        .filter(|entry| {
-            entry
-                .extension()
-                .is_some_and(|ext| ext == audio_ext || ext == line_ext)
-        })
-        .collect())
+    {
+        let entry = ok_entry.path();
+        // シンボリックリンクのフォルダはループする可能性があるため辿らない
+        if ok_entry.file_type().is_ok_and(|t| t.is_dir()) {
+            if depth > 0 && !skip.contains(&entry) && !entry.join(JOURNAL_FILE_NAME).exists() {
+                list.extend(get_file_list(&entry, matcher, depth - 1, skip)?);
+            }
+        } else if matcher.is_audio(&entry) || matcher.is_line(&entry) || matcher.is_bare(&entry) {
+            list.push(entry);
+        }
+    }
+    Ok(list)
 }
 
-fn create_renamed_folder<P: AsRef<Path>>(dir: P) -> Result<(), Error> {
-    fs::create_dir(dir.as_ref().join("renamed")).map_err(Error::FailedCreateRenamedFolder)?;
+/// `Merge`の場合は既にあってもエラーにしない
+fn create_renamed_folder<P: AsRef<Path>>(dir: P, policy: ExistingDirPolicy) -> Result<(), Error> {
+    match policy {
+        ExistingDirPolicy::Merge => fs::create_dir_all(&dir),
+        _ => fs::create_dir(&dir),
+    }
+    .map_err(Error::io(Operation::CreateDir, &dir))?;
     Ok(())
 }
 
-/// リスト中のオーディオファイルパスから、対応するテキストファイルからセリフを20文字にカットし、Vec<Pathset>として返す
-fn build_path_sets(
-    list: Vec<PathBuf>,
-    audio_ext: &str,
-    line_ext: &str,
-) -> Result<Vec<PathSet>, Error> {
+/// リスト中のオーディオファイルパスから、`matcher.line_sources`の順にセリフを探し、Vec<Pathset>として返す
+fn build_path_sets(list: Vec<PathBuf>, matcher: &ExtMatcher) -> Result<Vec<PathSet>, Error> {
     let mut tmp_list = Vec::<PathSet>::new();
     let mut empty_count = 0;
 
+    // 拡張子を除いたパス -> テキストファイルのパス
+    let line_files = list
+        .iter()
+        .filter(|p| matcher.is_line(p))
+        .map(|p| (p.with_extension(""), p.clone()))
+        .collect::<HashMap<_, _>>();
+
     for path in list {
-        if match path.extension() {
-            Some(v) => v,
-            None => continue,
-        } == audio_ext
-        {
+        if matcher.is_audio(&path) {
             // パスを探す
-            let text_path = path.with_extension(line_ext);
-
-            let mut empty = false;
-
-            let line = if text_path.exists() {
-                let tmp = fs::read_to_string(text_path)
-                    .map_err(Error::IoError)?
-                    .chars()
-                    .take(20)
-                    .collect::<String>()
-                    .trim()
-                    .to_string();
-                if tmp.is_empty() {
-                    // テキストファイルはあったが、セリフが空だった場合
-                    empty = true;
-                    format!("empty_{}", empty_count)
-                } else {
-                    // セリフがあった場合
-                    tmp
-                }
-            } else {
-                // テキストファイルがなかった場合
-                empty = true;
-                format!("empty_{}", empty_count)
-            };
+            let text_path = line_files.get(&path.with_extension(""));
+            let parsed = path
+                .file_stem()
+                .and_then(|s| matcher.filename_parser.parse(&s.to_string_lossy()))
+                .unwrap_or_default();
 
-            if empty {
-                empty_count += 1;
+            let mut found = None;
+            for source in matcher.line_sources {
+                // 切るのはready_rename()で行う
+                let line = match source {
+                    LineSource::Text => match text_path {
+                        // UTF-8以外(Shift_JIS, UTF-16など)の場合もあるため
+                        Some(text_path) => encoding::decode(
+                            &fs::read(text_path)
+                                .map_err(Error::io(Operation::ReadLine, text_path))?,
+                            matcher.fallback_encoding,
+                        ),
+                        None => continue,
+                    },
+                    LineSource::Filename => match &parsed.line {
+                        Some(v) => v.clone(),
+                        None => continue,
+                    },
+                    LineSource::Transcription => {
+                        match transcribe(matcher.transcription_model, &path)? {
+                            Some(v) => v,
+                            None => continue,
+                        }
+                    }
+                    _ => continue,
+                };
+                let line = line.trim();
+                if !line.is_empty() {
+                    found = Some((line.to_string(), *source));
+                    break;
+                }
             }
 
-            let new_set = PathSet::new(path, line);
+            let mut new_set = match found {
+                Some((line, source)) => {
+                    let mut set = PathSet::new(&path, line);
+                    set.line_source = source;
+                    set
+                }
+                None => {
+                    let mut set = PathSet::new(&path, format!("empty_{}", empty_count));
+                    empty_count += 1;
+                    // テキストファイルはあったが、セリフが空だった場合はEmpty
+                    set.status = match text_path {
+                        Some(_) => LineStatus::Empty,
+                        None => LineStatus::Missing,
+                    };
+                    set
+                }
+            };
+            new_set.parsed = parsed;
             tmp_list.push(new_set);
         }
     }
@@ -280,13 +1422,29 @@ fn build_path_sets(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{env, process};
+    use std::{env, path::PathBuf, process};
+
+    /// copy assets_for_test/source to a fresh temp dir named `name` for test.
+    /// 他のテストとassetsを共有しないためのもの
+    pub(crate) fn ready_in(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join("track2line_lib_test").join(name);
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        let source = env::current_dir().unwrap().join("assets_for_test/source");
+        for entry in fs::read_dir(source).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+        }
+        dir
+    }
 
     #[test]
     fn ready_foo() {
         ready();
         let a = env::current_dir().unwrap().join("assets_for_test/assets");
-        create_renamed_folder(a).unwrap();
+        create_renamed_folder(a.join("renamed"), ExistingDirPolicy::Fail).unwrap();
     }
 
     /// run ready_test_files.ps1 or ready_test_files.sh for test ready
@@ -326,8 +1484,17 @@ mod tests {
                     .unwrap()
                     .join("assets_for_test")
                     .join("assets"),
-                "wav",
-                "txt"
+                &ExtMatcher {
+                    audio: &["wav".to_string()],
+                    line: "txt",
+                    ignore_case: false,
+                    fallback_encoding: encoding::default_fallback(),
+                    filename_parser: &FilenameParser::default(),
+                    line_sources: &[LineSource::Text],
+                    transcription_model: None,
+                },
+                0,
+                &[]
             )
             .unwrap()
         );
@@ -364,7 +1531,8 @@ mod tests {
             .unwrap()
             .join("assets_for_test")
             .join("assets");
-        PathSets::new(&cud, "wav", "txt").unwrap().rename().unwrap();
+        let report = PathSets::new(&cud, "wav", "txt").unwrap().rename().unwrap();
+        println!("{}", report);
         for i in fs::read_dir(cud.join("renamed")).unwrap() {
             println!("{:?}", i);
         }
@@ -381,4 +1549,710 @@ mod tests {
         b.ready_rename();
         println!("{:?}", b.list);
     }
+
+    #[test]
+    fn test_collision() {
+        let dir = ready_in("collision");
+        fs::copy(dir.join("Talk1_1.wav"), dir.join("dup.wav")).unwrap();
+        fs::copy(dir.join("Talk1_1.txt"), dir.join("dup.txt")).unwrap();
+
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        let check = sets.check().unwrap();
+        println!("{}", check);
+        assert_eq!(check.collisions().len(), 1);
+        assert_eq!(check.collisions()[0].sources.len(), 2);
+        let names = sets
+            .list
+            .iter()
+            .filter_map(|i| i.changed_audio_path.clone())
+            .collect::<HashSet<_>>();
+        assert_eq!(names.len(), sets.list.len());
+
+        // `{index}`と同じ1からの番号
+        sets.set_collision_policy(CollisionPolicy::IndexPrefix);
+        let second = sets.check().unwrap().collisions()[0].sources[1].clone();
+        let n = sets
+            .list
+            .iter()
+            .position(|i| i.audio_path == second)
+            .unwrap();
+        let prefixed = file_name_of(sets.list[n].changed_audio_path.as_ref().unwrap());
+        println!("{}", prefixed);
+        assert!(prefixed.starts_with(&format!("{}_", n + 1)));
+
+        sets.set_collision_policy(CollisionPolicy::Abort);
+        assert!(matches!(sets.rename(), Err(Error::Collision(_))));
+        assert!(!dir.join("renamed").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_template() {
+        let dir = ready_in("template");
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        assert!(sets.set_template("{index:03}_{unknown}").is_err());
+        sets.set_template("{index:03}_{stem}__{line}").unwrap();
+        let check = sets.check().unwrap();
+        println!("{}", check);
+        assert!(check
+            .list
+            .iter()
+            .all(|(_, new, _)| new.as_ref().is_some_and(|n| n.contains("__"))));
+        assert!(sets.list.iter().any(|i| i.changed_audio_path
+            == Some(dir.join("renamed").join(format!(
+                "001_{}__{}.wav",
+                i.audio_path.file_stem().unwrap().to_string_lossy(),
+                sets.truncation.truncate(&i.line)
+            )))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rename_report() {
+        let dir = ready_in("rename_report");
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        // 計画後に元のファイルが消えた場合
+        fs::remove_file(&sets.list[0].audio_path).unwrap();
+
+        let report = sets.rename().unwrap();
+        println!("{}", report);
+        let summary = report.summary();
+        assert!(!report.is_ok());
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.renamed, sets.list.len() - 1);
+        assert!(matches!(
+            report.entries[0].outcome,
+            RenameOutcome::Failed(_)
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_output_dir() {
+        let dir = ready_in("output_dir");
+        let out = env::temp_dir()
+            .join("track2line_lib_test")
+            .join("output_dir_out");
+        if out.exists() {
+            fs::remove_dir_all(&out).unwrap();
+        }
+        fs::create_dir(&out).unwrap();
+
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.set_output_dir(&out).unwrap();
+        assert!(sets.rename().is_err());
+
+        sets.set_existing_dir_policy(ExistingDirPolicy::Fresh)
+            .unwrap();
+        assert_eq!(sets.output_dir(), out.with_file_name("output_dir_out_1"));
+        sets.set_existing_dir_policy(ExistingDirPolicy::Merge)
+            .unwrap();
+        assert_eq!(sets.output_dir(), out);
+        let target = sets.list[0].changed_audio_path.clone().unwrap();
+        assert!(target.starts_with(&out));
+
+        // 計画後に変更後のパスにファイルができた場合
+        fs::write(&target, "").unwrap();
+        let report = sets.rename().unwrap();
+        assert_eq!(report.summary().collided, 1);
+        assert!(sets.journal_path().exists());
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(out).unwrap();
+    }
+
+    #[test]
+    fn test_output_dir_not_scanned() {
+        let dir = ready_in("output_dir_not_scanned");
+        fs::create_dir(dir.join("out")).unwrap();
+        fs::copy(dir.join("Talk1_1.wav"), dir.join("out").join("prev.wav")).unwrap();
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.set_max_depth(usize::MAX).unwrap();
+        sets.set_existing_dir_policy(ExistingDirPolicy::Merge)
+            .unwrap();
+        sets.set_output_dir(dir.join("out")).unwrap();
+        assert!(!sets
+            .list
+            .iter()
+            .any(|i| i.audio_path.starts_with(dir.join("out"))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_sidecars() {
+        let dir = ready_in("sidecars");
+        fs::write(dir.join("Talk1_1.lab"), "0 1 a").unwrap();
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.set_sidecars(&["txt", "lab", "json"]);
+        println!("{}", sets.check().unwrap());
+        let set = sets
+            .list
+            .iter()
+            .find(|i| i.audio_path.ends_with("Talk1_1.wav"))
+            .unwrap();
+        assert_eq!(set.sidecars.len(), 2);
+        let new_stem = set.changed_audio_path.as_ref().unwrap().with_extension("");
+
+        let report = sets.rename().unwrap();
+        assert!(report.is_ok());
+        assert!(new_stem.with_extension("lab").exists());
+        assert!(new_stem.with_extension("txt").exists());
+        assert!(!dir.join("Talk1_1.txt").exists());
+
+        assert!(sets.rollback().unwrap().is_ok());
+        assert!(dir.join("Talk1_1.lab").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_sidecar_collision() {
+        let dir = ready_in("sidecar_collision");
+        fs::copy(dir.join("Talk1_1.wav"), dir.join("a.wav")).unwrap();
+        fs::write(dir.join("a.txt"), "hello").unwrap();
+        fs::copy(dir.join("english_test.mp3"), dir.join("b.mp3")).unwrap();
+        fs::write(dir.join("b.txt"), "hello").unwrap();
+        // 同じセリフのファイルを持つ音声ファイル
+        fs::copy(dir.join("Talk1_1.wav"), dir.join("Talk1_1.mp3")).unwrap();
+
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.set_audio_extensions(&["wav", "mp3"]).unwrap();
+        sets.set_sidecars(&["txt"]);
+        let check = sets.check().unwrap();
+        println!("{}", check);
+        // hello.wavとhello.mp3は違うが、hello.txtが重複する
+        assert_eq!(check.collisions().len(), 1);
+        assert!(check.collisions()[0].target.ends_with("hello.txt"));
+        let talk_txt = sets
+            .list
+            .iter()
+            .filter(|i| {
+                i.sidecars
+                    .iter()
+                    .any(|(from, _)| from == &dir.join("Talk1_1.txt"))
+            })
+            .count();
+        assert_eq!(talk_txt, 1);
+
+        let report = sets.rename().unwrap();
+        println!("{}", report);
+        assert!(report.is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_sidecars_ignore_case() {
+        let dir = ready_in("sidecars_ignore_case");
+        fs::rename(dir.join("Talk1_2.txt"), dir.join("Talk1_2.TXT")).unwrap();
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.set_ignore_case(true).unwrap();
+        sets.set_sidecars(&["txt"]);
+        let set = sets
+            .list
+            .iter()
+            .find(|i| i.audio_path.ends_with("Talk1_2.wav"))
+            .unwrap();
+        assert_eq!(set.sidecars.len(), 1);
+        assert_eq!(set.sidecars[0].0, dir.join("Talk1_2.TXT"));
+        assert!(set.sidecars[0].1.to_string_lossy().ends_with(".TXT"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_recursive() {
+        let dir = ready_in("recursive");
+        let scene = dir.join("scene1").join("cut1");
+        fs::create_dir_all(&scene).unwrap();
+        fs::copy(dir.join("Talk1_1.wav"), scene.join("Talk1_1.wav")).unwrap();
+        fs::copy(dir.join("Talk1_1.txt"), scene.join("Talk1_1.txt")).unwrap();
+
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        let top = sets.list.len();
+        sets.set_max_depth(1).unwrap();
+        assert_eq!(sets.list.len(), top);
+        sets.set_max_depth(usize::MAX).unwrap();
+        assert_eq!(sets.list.len(), top + 1);
+        // 違うフォルダなので重複ではない
+        assert!(sets.collisions.is_empty());
+
+        let report = sets.rename().unwrap();
+        assert!(report.is_ok());
+        let renamed = sets
+            .list
+            .iter()
+            .find(|i| i.audio_path.starts_with(&scene))
+            .and_then(|i| i.changed_audio_path.clone())
+            .unwrap();
+        assert!(renamed.starts_with(dir.join("renamed").join("scene1").join("cut1")));
+        assert!(renamed.exists());
+
+        // 出力フォルダは読まない
+        fs::create_dir(dir.join("more")).unwrap();
+        fs::copy(&renamed, dir.join("more").join("a.wav")).unwrap();
+        sets.set_max_depth(usize::MAX).unwrap();
+        assert_eq!(sets.list.len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_audio_extensions() {
+        let dir = ready_in("audio_extensions");
+        fs::rename(dir.join("Talk1_1.wav"), dir.join("Talk1_1.WAV")).unwrap();
+        fs::rename(dir.join("Talk1_2.txt"), dir.join("Talk1_2.TXT")).unwrap();
+
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        let wav = sets.list.len();
+        sets.set_ignore_case(true).unwrap();
+        assert_eq!(sets.list.len(), wav + 1);
+        sets.set_audio_extensions(&["wav", "mp3"]).unwrap();
+        println!("{}", sets.check().unwrap());
+
+        let ext_of = |name: &str| {
+            let set = sets
+                .list
+                .iter()
+                .find(|i| i.audio_path.ends_with(name))
+                .unwrap();
+            (
+                set.status,
+                set.changed_audio_path
+                    .as_ref()
+                    .and_then(|p| p.extension())
+                    .map(|e| e.to_string_lossy().to_string()),
+            )
+        };
+        assert_eq!(
+            ext_of("Talk1_1.WAV"),
+            (LineStatus::Ok, Some("WAV".to_string()))
+        );
+        assert_eq!(ext_of("Talk1_2.wav").0, LineStatus::Ok);
+        assert_eq!(ext_of("english_test.mp3").1, Some("mp3".to_string()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_encoding() {
+        let dir = ready_in("encoding");
+        let text = "これはテストです。";
+        fs::write(
+            dir.join("Talk1_1.txt"),
+            encoding_rs::SHIFT_JIS.encode(text).0,
+        )
+        .unwrap();
+        fs::write(dir.join("Talk1_2.txt"), format!("\u{feff}{}", text)).unwrap();
+
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        let line_of = |sets: &PathSets, name: &str| {
+            sets.list
+                .iter()
+                .find(|i| i.audio_path.ends_with(name))
+                .map(|i| i.line.clone())
+                .unwrap()
+        };
+        assert_eq!(line_of(&sets, "Talk1_1.wav"), text);
+        assert_eq!(line_of(&sets, "Talk1_2.wav"), text);
+
+        fs::write(dir.join("Talk1_1.txt"), encoding_rs::EUC_JP.encode(text).0).unwrap();
+        sets.set_fallback_encoding("euc-jp").unwrap();
+        assert_eq!(line_of(&sets, "Talk1_1.wav"), text);
+        assert!(sets.set_fallback_encoding("unknown").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_filename_parser() {
+        let dir = ready_in("filename_parser");
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.set_template("{number:03}_{speaker}（{style}）_{line}")
+            .unwrap();
+        let stem = "001_つくよみちゃん（れいせい）_これはテストです。";
+        let entry = sets
+            .list
+            .iter()
+            .find(|i| i.audio_path.ends_with(format!("{}.wav", stem)))
+            .unwrap();
+        // テキストファイルが無いため、ファイル名のセリフを使う
+        assert_eq!(entry.status, LineStatus::Ok);
+        assert_eq!(entry.line, "これはテストです。");
+        assert_eq!(
+            entry
+                .changed_audio_path
+                .as_ref()
+                .unwrap()
+                .file_stem()
+                .unwrap(),
+            stem
+        );
+
+        sets.set_filename_parser(FilenameParser::empty()).unwrap();
+        let entry = sets
+            .list
+            .iter()
+            .find(|i| i.audio_path.ends_with(format!("{}.wav", stem)))
+            .unwrap();
+        assert_eq!(entry.status, LineStatus::Missing);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_line_sources() {
+        let dir = ready_in("line_sources");
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        // 音声認識は明示した場合のみ
+        assert!(!sets.line_sources.contains(&LineSource::Transcription));
+        let source_of = |sets: &PathSets, name: &str| {
+            sets.plan()
+                .rows
+                .into_iter()
+                .find(|r| r.source.file_stem().is_some_and(|s| s == name))
+                .map(|r| (r.line_source, r.status))
+                .unwrap()
+        };
+        let stem = "001_つくよみちゃん（れいせい）_これはテストです。";
+        assert_eq!(
+            source_of(&sets, stem),
+            (LineSource::Filename, LineStatus::Ok)
+        );
+        assert_eq!(
+            source_of(&sets, "Talk1_1"),
+            (LineSource::Text, LineStatus::Ok)
+        );
+        assert_eq!(
+            source_of(&sets, "non_line"),
+            (LineSource::Placeholder, LineStatus::Empty)
+        );
+
+        sets.set_line_sources(&[LineSource::Text]).unwrap();
+        assert_eq!(
+            source_of(&sets, stem),
+            (LineSource::Placeholder, LineStatus::Missing)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_script() {
+        let dir = ready_in("script");
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.set_audio_extensions(&["mp3"]).unwrap();
+        sets.set_template("{speaker}_{line}").unwrap();
+        // mp3は4つ
+        let script = Script::parse(
+            "speaker,line\nA,一つ目\nB,二つ目\nC,三つ目\n",
+            script::ScriptFormat::Csv,
+        )
+        .unwrap();
+        sets.set_script(Some(script.clone())).unwrap();
+        let check = sets.check().unwrap();
+        println!("{}", check);
+        assert_eq!(
+            check.script_mismatch(),
+            Some(ScriptMismatch { lines: 3, audio: 4 })
+        );
+        assert!(matches!(sets.rename(), Err(Error::ScriptMismatch(_))));
+        assert!(!sets.output_dir().exists());
+
+        let mut script = script;
+        script.lines.push(script::ScriptLine {
+            speaker: None,
+            line: "四つ目".to_string(),
+        });
+        sets.set_script(Some(script)).unwrap();
+        assert!(sets.check().unwrap().script_mismatch().is_none());
+        let names = sets
+            .plan()
+            .rows
+            .iter()
+            .map(|r| file_name_of(r.target.as_ref().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "A_一つ目.mp3",
+                "B_二つ目.mp3",
+                "C_三つ目.mp3",
+                "_四つ目.mp3"
+            ]
+        );
+        assert_eq!(
+            sets.plan().rows[0].source,
+            dir.join("but_this_code_dont_compile.mp3")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_sort_order() {
+        let dir = ready_in("sort_order");
+        for (name, line) in [("Talk1_10", "十"), ("Talk1_9", "九"), ("a_5", "五")] {
+            fs::copy(dir.join("Talk1_1.wav"), dir.join(format!("{}.wav", name))).unwrap();
+            fs::write(dir.join(format!("{}.txt", name)), line).unwrap();
+        }
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        let stems = |sets: &PathSets| {
+            sets.list
+                .iter()
+                .map(|i| {
+                    i.audio_path
+                        .file_stem()
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+        let natural = stems(&sets);
+        println!("{:?}", natural);
+        let pos = |v: &[String], s: &str| v.iter().position(|i| i == s).unwrap();
+        assert!(pos(&natural, "Talk1_9") < pos(&natural, "Talk1_10"));
+        assert!(natural
+            .windows(2)
+            .all(|w| order::natural_cmp(&w[0], &w[1]).is_le()));
+        // 何度読んでも同じ
+        sets.set_max_depth(0).unwrap();
+        assert_eq!(stems(&sets), natural);
+
+        sets.set_sort_order(SortOrder::Index).unwrap();
+        let by_index = stems(&sets);
+        println!("{:?}", by_index);
+        // 001_つくよみちゃん..., Talk1_1の番号は1
+        assert_eq!(pos(&by_index, "a_5"), 5);
+        assert_eq!(pos(&by_index, "Talk1_10"), 7);
+        assert_eq!(pos(&by_index, "non_line"), by_index.len() - 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_error() {
+        let dir = env::temp_dir()
+            .join("track2line_lib_test")
+            .join("not_found");
+        let e = PathSets::new(&dir, "wav", "txt").unwrap_err();
+        println!("{}", e);
+        assert!(matches!(
+            e,
+            Error::Io {
+                op: Operation::Scan,
+                ..
+            }
+        ));
+        assert_eq!(e.path(), Some(dir.as_path()));
+        assert!(std::error::Error::source(&e).is_some());
+        assert!(!e.to_string().ends_with('\n'));
+
+        // 壊れたjournal
+        let dir = ready_in("error_journal");
+        let journal = dir.join(JOURNAL_FILE_NAME);
+        fs::write(&journal, "entries = 1").unwrap();
+        let e = Journal::load(&journal).unwrap_err();
+        println!("{}", e);
+        assert!(matches!(e, Error::InvalidJournal { .. }));
+        assert_eq!(e.path(), Some(journal.as_path()));
+        assert!(std::error::Error::source(&e).is_some());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        let dir = ready_in("validate");
+        fs::write(dir.join("orphan.txt"), "音声が無い").unwrap();
+        fs::copy(dir.join("Talk1_1.wav"), dir.join(".wav")).unwrap();
+        fs::copy(dir.join("Talk1_1.wav"), dir.join("no_text.wav")).unwrap();
+        fs::copy(dir.join("Talk1_1.wav"), dir.join("long.wav")).unwrap();
+        fs::write(dir.join("long.txt"), "あ".repeat(30)).unwrap();
+        fs::copy(dir.join("Talk1_1.wav"), dir.join("dup.wav")).unwrap();
+        fs::copy(dir.join("Talk1_1.txt"), dir.join("dup.txt")).unwrap();
+
+        let sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        let diagnostics = sets.validate();
+        for i in &diagnostics {
+            println!("{}", i);
+        }
+        let has = |name: &str, kind: fn(&DiagnosticKind) -> bool| {
+            diagnostics
+                .iter()
+                .any(|d| d.path == dir.join(name) && kind(&d.kind))
+        };
+        assert!(has("orphan.txt", |k| *k == DiagnosticKind::OrphanText));
+        assert!(has(".wav", |k| *k == DiagnosticKind::HiddenFile));
+        assert!(has("no_text.wav", |k| *k == DiagnosticKind::MissingText));
+        assert!(has("non_line.wav", |k| *k == DiagnosticKind::EmptyText));
+        assert!(has("include_whs_end .wav", |k| {
+            *k == DiagnosticKind::WhitespaceStem
+        }));
+        assert!(has("long.wav", |k| matches!(
+            k,
+            DiagnosticKind::LongLine { length: 30, .. }
+        )));
+        assert!(has("dup.wav", |k| matches!(
+            k,
+            DiagnosticKind::DuplicateTarget { resolved: true, .. }
+        )));
+        // 0バイト
+        assert!(has("non_line.wav", |k| matches!(
+            k,
+            DiagnosticKind::UnreadableAudio(_)
+        )));
+        assert!(!diagnostics.iter().any(|d| d.is_error()));
+        let unreadable = diagnostics
+            .iter()
+            .find(|d| {
+                d.path == dir.join("non_line.wav")
+                    && matches!(d.kind, DiagnosticKind::UnreadableAudio(_))
+            })
+            .unwrap()
+            .to_string();
+        assert_eq!(unreadable.matches("non_line.wav").count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate_mp3() {
+        let dir = ready_in("validate_mp3");
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.set_audio_extensions(&["wav", "mp3"]).unwrap();
+        let kinds = sets
+            .validate()
+            .into_iter()
+            .filter(|d| d.path == dir.join("english_test.mp3"))
+            .map(|d| d.kind)
+            .collect::<Vec<_>>();
+        println!("{:?}", kinds);
+        assert!(!kinds
+            .iter()
+            .any(|k| matches!(k, DiagnosticKind::UnreadableAudio(_))));
+        assert_eq!(
+            kinds.contains(&DiagnosticKind::UnsupportedAudio),
+            cfg!(not(feature = "decode"))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_timeline() {
+        let dir = ready_in("timeline");
+        // 0バイトの音声ファイルは読めないため
+        fs::remove_file(dir.join("include_whs_end .wav")).unwrap();
+        fs::remove_file(dir.join("non_line.wav")).unwrap();
+        let sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        let gap = Duration::from_millis(500);
+        let timeline = sets.timeline(gap).unwrap();
+        assert_eq!(timeline.clips.len(), sets.list.len());
+        for w in timeline.clips.windows(2) {
+            assert_eq!(w[1].start, w[0].end() + gap);
+        }
+        // Talk1_1.wav: 48kHz, 16bit, mono
+        let talk = timeline
+            .clips
+            .iter()
+            .find(|c| c.source.ends_with("Talk1_1.wav"))
+            .unwrap();
+        assert_eq!(
+            talk.duration,
+            Duration::from_secs_f64(
+                (fs::metadata(&talk.source).unwrap().len() - 44) as f64 / 96000.0
+            )
+        );
+
+        let srt = subtitle::Subtitles::default().to_srt(&timeline);
+        println!("{}", srt);
+        // but_this_code_dont_compile(セリフ無し)以外
+        assert_eq!(srt.matches(" --> ").count(), timeline.clips.len() - 1);
+
+        fs::write(dir.join("broken.wav"), "not a wav").unwrap();
+        let sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        assert!(matches!(sets.timeline(gap), Err(Error::InvalidAudio(..))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_journal_before_rename() {
+        let dir = ready_in("journal_before_rename");
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.set_existing_dir_policy(ExistingDirPolicy::Merge)
+            .unwrap();
+        // journalを保存できない場合は何も移動しない
+        fs::create_dir_all(sets.journal_path()).unwrap();
+        assert!(matches!(sets.rename(), Err(Error::Io { .. })));
+        assert!(dir.join("Talk1_1.wav").exists());
+
+        fs::remove_dir(sets.journal_path()).unwrap();
+        let report = sets.rename().unwrap();
+        assert!(report.is_ok());
+        let journal = Journal::load(sets.journal_path()).unwrap();
+        let renamed = report
+            .entries
+            .iter()
+            .flat_map(|i| [i].into_iter().chain(&i.sidecars))
+            .filter(|i| matches!(i.outcome, RenameOutcome::Renamed))
+            .count();
+        assert_eq!(journal.entries.len(), renamed);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_concat() {
+        let dir = ready_in("concat");
+        let out = env::temp_dir().join("track2line_concat");
+        fs::create_dir_all(&out).unwrap();
+        let wav = out.join("joined.wav");
+        let gap = Duration::from_millis(500);
+
+        // 44.1kHzと48kHzが混ざっている
+        fs::remove_file(dir.join("include_whs_end .wav")).unwrap();
+        fs::remove_file(dir.join("non_line.wav")).unwrap();
+        let sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        match sets.concat(&wav, gap) {
+            Err(Error::MismatchedAudio(list)) => assert!(!list.is_empty()),
+            e => panic!("{:?}", e),
+        }
+        assert!(!wav.exists());
+
+        // 48kHzのTalk1_*のみ
+        fs::remove_file(dir.join("but_this_code_dont_compile.wav")).unwrap();
+        for e in fs::read_dir(&dir).unwrap() {
+            let path = e.unwrap().path();
+            if path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with("001_")
+            {
+                fs::remove_file(path).unwrap();
+            }
+        }
+        let mut sets = PathSets::new(&dir, "wav", "txt").unwrap();
+        sets.rename().unwrap();
+        let joined = sets.concat(&wav, gap).unwrap();
+        assert_eq!(joined.clips.len(), 4);
+
+        let reader = hound::WavReader::open(&wav).unwrap();
+        assert_eq!(reader.spec().sample_rate, 48000);
+        let silence = (gap.as_secs_f64() * 48000.0) as u32 * 3;
+        let samples = sets
+            .list
+            .iter()
+            .map(|i| {
+                (fs::metadata(i.changed_audio_path.as_ref().unwrap())
+                    .unwrap()
+                    .len()
+                    - 44)
+                    / 2
+            })
+            .sum::<u64>() as u32;
+        assert_eq!(reader.duration(), samples + silence);
+        let expected = reader.duration() as f64 / 48000.0;
+        assert!((joined.duration().as_secs_f64() - expected).abs() < 1e-6);
+
+        let cue = fs::read_to_string(wav.with_extension("cue")).unwrap();
+        println!("{}", cue);
+        assert!(cue.starts_with("FILE \"joined.wav\" WAVE"));
+        assert_eq!(cue.matches("INDEX 01").count(), 4);
+        assert!(cue.contains("INDEX 01 00:00:00"));
+        assert!(!wav.with_extension("txt").exists());
+        let labels = fs::read_to_string(wav.with_extension("labels.txt")).unwrap();
+        assert_eq!(labels.lines().count(), 4);
+        assert!(labels.starts_with("0.000000\t"));
+
+        fs::remove_dir_all(out).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,394 @@
+use crate::{
+    timeline::{frames, Clip, Timeline},
+    Error, Operation,
+};
+use std::{
+    fmt::Write,
+    fs,
+    path::{self, Path},
+};
+
+/// where to put the dialogue of each clip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DialoguePlacement {
+    /// クリップ名をセリフにする。セリフが無い場合はファイル名
+    #[default]
+    ClipName,
+    /// クリップ名はファイル名のまま、セリフをマーカーにする
+    Marker,
+}
+
+/// exporter of a `Timeline` for video editors (FCPXML, CMX3600 EDL, Premiere XML).
+/// クリップの位置と間隔は`PathSets::timeline()`で決まる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NleExport {
+    title: String,
+    frame_rate: u32,
+    dialogue: DialoguePlacement,
+    track_per_speaker: bool,
+}
+impl Default for NleExport {
+    /// 30fps、セリフはクリップ名、全て1つのトラック
+    fn default() -> Self {
+        Self {
+            title: "track2line".to_string(),
+            frame_rate: 30,
+            dialogue: DialoguePlacement::default(),
+            track_per_speaker: false,
+        }
+    }
+}
+impl NleExport {
+    /// シーケンス(プロジェクト)の名前
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    /// タイムコードのフレームレート(ノンドロップ)。0の場合は1にする
+    pub fn set_frame_rate(&mut self, fps: u32) {
+        self.frame_rate = fps.max(1);
+    }
+
+    pub fn set_dialogue(&mut self, dialogue: DialoguePlacement) {
+        self.dialogue = dialogue;
+    }
+
+    /// 話者ごとにトラックを分けるか。話者が無いクリップは1つ目のトラック
+    pub fn set_track_per_speaker(&mut self, split: bool) {
+        self.track_per_speaker = split;
+    }
+
+    /// Final Cut Pro X (FCPXML 1.9).
+    /// 全体の長さのギャップに、トラックごとのレーンで音声クリップを付ける
+    pub fn to_fcpxml(&self, timeline: &Timeline) -> String {
+        let fps = self.frame_rate;
+        let time = |frames: u64| format!("{}/{}s", frames, fps);
+        let clips = self.clips(timeline);
+        let total = clips
+            .iter()
+            .map(|c| c.start + c.duration)
+            .max()
+            .unwrap_or(0);
+
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE fcpxml>\n");
+        out.push_str("<fcpxml version=\"1.9\">\n  <resources>\n");
+        let _ = writeln!(
+            out,
+            "    <format id=\"r0\" frameDuration=\"1/{}s\" width=\"1920\" height=\"1080\"/>",
+            fps
+        );
+        for (n, c) in clips.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "    <asset id=\"r{}\" name=\"{}\" start=\"0s\" duration=\"{}\" hasAudio=\"1\">\n      <media-rep kind=\"original-media\" src=\"{}\"/>\n    </asset>",
+                n + 1,
+                escape(&c.file_name),
+                time(c.duration),
+                escape(&file_url(&c.clip.path))
+            );
+        }
+        out.push_str("  </resources>\n  <library>\n");
+        let title = escape(&self.title);
+        let _ = writeln!(
+            out,
+            "    <event name=\"{}\">\n      <project name=\"{}\">\n        <sequence format=\"r0\" duration=\"{}\" tcStart=\"0s\" tcFormat=\"NDF\">\n          <spine>\n            <gap name=\"Gap\" offset=\"0s\" start=\"0s\" duration=\"{}\">",
+            title,
+            title,
+            time(total),
+            time(total)
+        );
+        for (n, c) in clips.iter().enumerate() {
+            let _ = write!(
+                out,
+                "              <asset-clip ref=\"r{}\" lane=\"-{}\" offset=\"{}\" name=\"{}\" start=\"0s\" duration=\"{}\" audioRole=\"dialogue\"",
+                n + 1,
+                c.track + 1,
+                time(c.start),
+                escape(&c.name),
+                time(c.duration)
+            );
+            match &c.marker {
+                Some(m) => {
+                    let _ = writeln!(
+                        out,
+                        ">\n                <marker start=\"0s\" duration=\"{}\" value=\"{}\"/>\n              </asset-clip>",
+                        time(1),
+                        escape(m)
+                    );
+                }
+                None => out.push_str("/>\n"),
+            }
+        }
+        out.push_str("            </gap>\n          </spine>\n        </sequence>\n      </project>\n    </event>\n  </library>\n</fcpxml>\n");
+        out
+    }
+
+    /// CMX3600 EDL. トラックは`A`, `A2`, `A3`...
+    /// セリフは`* FROM CLIP NAME:`(`ClipName`)または`* LOC:`(`Marker`)に書く
+    pub fn to_edl(&self, timeline: &Timeline) -> String {
+        let fps = self.frame_rate;
+        let mut out = String::new();
+        let _ = writeln!(out, "TITLE: {}\nFCM: NON-DROP FRAME\n", self.title);
+        for (n, c) in self.clips(timeline).iter().enumerate() {
+            let track = match c.track {
+                0 => "A".to_string(),
+                t => format!("A{}", t + 1),
+            };
+            let _ = writeln!(
+                out,
+                "{:03}  AX       {:<5} C        {} {} {} {}",
+                n + 1,
+                track,
+                timecode(0, fps),
+                timecode(c.duration, fps),
+                timecode(c.start, fps),
+                timecode(c.start + c.duration, fps)
+            );
+            // 改行を含むとEDLが壊れるため
+            let _ = writeln!(out, "* FROM CLIP NAME: {}", one_line(&c.name));
+            let _ = writeln!(out, "* SOURCE FILE: {}", c.clip.path.display());
+            if let Some(m) = &c.marker {
+                let _ = writeln!(
+                    out,
+                    "* LOC: {} YELLOW {}",
+                    timecode(c.start, fps),
+                    one_line(m)
+                );
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Premiere Pro / DaVinci Resolve (XMEML version 4).
+    pub fn to_xmeml(&self, timeline: &Timeline) -> String {
+        let fps = self.frame_rate;
+        let rate = format!(
+            "<rate><timebase>{}</timebase><ntsc>FALSE</ntsc></rate>",
+            fps
+        );
+        let clips = self.clips(timeline);
+        let total = clips
+            .iter()
+            .map(|c| c.start + c.duration)
+            .max()
+            .unwrap_or(0);
+        let tracks = clips.iter().map(|c| c.track + 1).max().unwrap_or(1);
+
+        let mut out = String::new();
+        out.push_str(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE xmeml>\n<xmeml version=\"4\">\n",
+        );
+        let _ = writeln!(
+            out,
+            "  <sequence>\n    <name>{}</name>\n    <duration>{}</duration>\n    {}\n    <media>\n      <audio>",
+            escape(&self.title),
+            total,
+            rate
+        );
+        for track in 0..tracks {
+            out.push_str("        <track>\n");
+            for (n, c) in clips.iter().enumerate().filter(|(_, c)| c.track == track) {
+                let _ = writeln!(
+                    out,
+                    "          <clipitem id=\"clipitem-{n}\">\n            <name>{}</name>\n            <duration>{}</duration>\n            {rate}\n            <start>{}</start>\n            <end>{}</end>\n            <in>0</in>\n            <out>{}</out>\n            <file id=\"file-{n}\">\n              <name>{}</name>\n              <pathurl>{}</pathurl>\n              {rate}\n              <duration>{}</duration>\n            </file>",
+                    escape(&c.name),
+                    c.duration,
+                    c.start,
+                    c.start + c.duration,
+                    c.duration,
+                    escape(&c.file_name),
+                    escape(&file_url(&c.clip.path)),
+                    c.duration,
+                    n = n + 1,
+                    rate = rate,
+                );
+                if let Some(m) = &c.marker {
+                    let _ = writeln!(
+                        out,
+                        "            <marker>\n              <name>{}</name>\n              <in>0</in>\n              <out>-1</out>\n            </marker>",
+                        escape(m)
+                    );
+                }
+                out.push_str("          </clipitem>\n");
+            }
+            out.push_str("        </track>\n");
+        }
+        out.push_str("      </audio>\n    </media>\n  </sequence>\n</xmeml>\n");
+        out
+    }
+
+    /// ファイルを上書きで保存する
+    pub fn save_fcpxml<P: AsRef<Path>>(&self, timeline: &Timeline, path: P) -> Result<(), Error> {
+        fs::write(&path, self.to_fcpxml(timeline)).map_err(Error::io(Operation::Write, &path))
+    }
+
+    /// ファイルを上書きで保存する
+    pub fn save_edl<P: AsRef<Path>>(&self, timeline: &Timeline, path: P) -> Result<(), Error> {
+        fs::write(&path, self.to_edl(timeline)).map_err(Error::io(Operation::Write, &path))
+    }
+
+    /// ファイルを上書きで保存する
+    pub fn save_xmeml<P: AsRef<Path>>(&self, timeline: &Timeline, path: P) -> Result<(), Error> {
+        fs::write(&path, self.to_xmeml(timeline)).map_err(Error::io(Operation::Write, &path))
+    }
+
+    /// フレーム単位の位置、トラック、名前、マーカーを決める
+    fn clips<'a>(&self, timeline: &'a Timeline) -> Vec<PlacedClip<'a>> {
+        timeline
+            .clips
+            .iter()
+            .zip(timeline.speaker_tracks())
+            .map(|(c, track)| {
+                let track = match self.track_per_speaker {
+                    true => track,
+                    false => 0,
+                };
+                let file_name = c
+                    .path
+                    .file_name()
+                    .map(|f| f.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let line = Some(c.line.trim().to_string()).filter(|l| !l.is_empty());
+                let (name, marker) = match self.dialogue {
+                    DialoguePlacement::ClipName => (line.unwrap_or(file_name.clone()), None),
+                    DialoguePlacement::Marker => (file_name.clone(), line),
+                };
+                let start = frames(c.start, self.frame_rate);
+                PlacedClip {
+                    clip: c,
+                    start,
+                    // 0フレームのクリップは読めないソフトがあるため
+                    duration: (frames(c.end(), self.frame_rate) - start).max(1),
+                    track,
+                    file_name,
+                    name,
+                    marker,
+                }
+            })
+            .collect()
+    }
+}
+
+/// フレーム単位に直したクリップ
+struct PlacedClip<'a> {
+    clip: &'a Clip,
+    start: u64,
+    duration: u64,
+    /// 0から
+    track: usize,
+    file_name: String,
+    name: String,
+    marker: Option<String>,
+}
+
+/// `hh:mm:ss:ff`
+fn timecode(frames: u64, fps: u32) -> String {
+    let fps = fps as u64;
+    let secs = frames / fps;
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        frames % fps
+    )
+}
+
+fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// `file:///`から始まるURL。ASCIIの英数字と`/-_.~`以外はパーセントエンコードする
+pub(crate) fn file_url(path: &Path) -> String {
+    let absolute = path::absolute(path).unwrap_or(path.to_path_buf());
+    let mut s = absolute.to_string_lossy().replace('\\', "/");
+    // Windowsの`C:/...`
+    if !s.starts_with('/') {
+        s.insert(0, '/');
+    }
+    let mut url = String::from("file://");
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                url.push(b as char)
+            }
+            _ => {
+                let _ = write!(url, "%{:02X}", b);
+            }
+        }
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{path::PathBuf, time::Duration};
+
+    fn timeline() -> Timeline {
+        let clip = |name: &str, start: u64, line: &str, speaker: Option<&str>| Clip {
+            source: PathBuf::from(name),
+            path: PathBuf::from("/out").join(name),
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(1500),
+            line: line.to_string(),
+            speaker: speaker.map(|s| s.to_string()),
+        };
+        Timeline {
+            clips: vec![
+                clip("a b.wav", 0, "こんにちは", Some("A")),
+                clip("2.wav", 2000, "<&>", Some("B")),
+                clip("3.wav", 4000, "", Some("A")),
+            ],
+        }
+    }
+
+    #[test]
+    fn edl() {
+        let mut export = NleExport::default();
+        export.set_track_per_speaker(true);
+        let edl = export.to_edl(&timeline());
+        println!("{}", edl);
+        assert!(edl.contains(
+            "001  AX       A     C        00:00:00:00 00:00:01:15 00:00:00:00 00:00:01:15"
+        ));
+        assert!(edl.contains(
+            "002  AX       A2    C        00:00:00:00 00:00:01:15 00:00:02:00 00:00:03:15"
+        ));
+        assert!(edl.contains("* FROM CLIP NAME: こんにちは"));
+        assert!(edl.contains("* FROM CLIP NAME: 3.wav"));
+
+        export.set_dialogue(DialoguePlacement::Marker);
+        let edl = export.to_edl(&timeline());
+        assert!(edl.contains("* FROM CLIP NAME: a b.wav"));
+        assert!(edl.contains("* LOC: 00:00:00:00 YELLOW こんにちは"));
+    }
+
+    #[test]
+    fn xml() {
+        let mut export = NleExport::default();
+        export.set_track_per_speaker(true);
+        let fcpxml = export.to_fcpxml(&timeline());
+        println!("{}", fcpxml);
+        assert!(fcpxml.contains("src=\"file:///out/a%20b.wav\""));
+        assert!(fcpxml.contains("lane=\"-2\" offset=\"60/30s\" name=\"&lt;&amp;&gt;\""));
+        assert_eq!(fcpxml.matches("<asset-clip ").count(), 3);
+
+        export.set_dialogue(DialoguePlacement::Marker);
+        let xmeml = export.to_xmeml(&timeline());
+        println!("{}", xmeml);
+        assert_eq!(xmeml.matches("<track>").count(), 2);
+        assert_eq!(xmeml.matches("<marker>").count(), 2);
+        assert!(xmeml.contains("<start>60</start>"));
+    }
+}
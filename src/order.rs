@@ -0,0 +1,115 @@
+use crate::filename::FilenameParser;
+use std::{
+    cmp::Ordering,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// order of the entries, which decides `{index}` and `empty_N`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    /// `work_dir`からの相対パスの自然順。`Talk1_2`は`Talk1_10`より前
+    #[default]
+    Natural,
+    /// 更新日時の古い順
+    Modified,
+    /// ファイル名から読み取った番号(`FilenameParser`)の順。番号が無いものは最後
+    Index,
+    /// `read_dir()`の順のまま。ファイルシステムによって変わる
+    None,
+}
+
+/// sort `list` by `order`. 同じ場合は自然順
+pub(crate) fn sort(list: &mut [PathBuf], order: SortOrder, base: &Path, parser: &FilenameParser) {
+    let relative = |p: &Path| {
+        p.strip_prefix(base)
+            .unwrap_or(p)
+            .to_string_lossy()
+            .to_string()
+    };
+    match order {
+        SortOrder::None => {}
+        SortOrder::Natural => list.sort_by(|a, b| natural_cmp(&relative(a), &relative(b))),
+        SortOrder::Modified => list.sort_by_cached_key(|p| {
+            let modified = fs::metadata(p).and_then(|m| m.modified()).ok();
+            (modified, NaturalKey(relative(p)))
+        }),
+        SortOrder::Index => list.sort_by_cached_key(|p| {
+            let index = p
+                .file_stem()
+                .and_then(|s| parser.parse(&s.to_string_lossy()))
+                .and_then(|p| p.index);
+            // Noneは最後にするため
+            (index.is_none(), index, NaturalKey(relative(p)))
+        }),
+    }
+}
+
+/// `natural_cmp()`で比べる文字列
+#[derive(PartialEq, Eq)]
+struct NaturalKey(String);
+impl PartialOrd for NaturalKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for NaturalKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        natural_cmp(&self.0, &other.0)
+    }
+}
+
+/// natural order comparison. `Talk1_2`は`Talk1_10`より前になる
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |it: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = it.next_if(|c| c.is_ascii_digit()) {
+                        digits.push(c);
+                    }
+                    digits
+                };
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                // 先頭の0を除いた桁数、値の順に比べ、同じなら0が少ない方を前にする
+                let (xt, yt) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ord = xt
+                    .len()
+                    .cmp(&yt.len())
+                    .then_with(|| xt.cmp(yt))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.cmp(&y);
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural() {
+        let mut names = vec!["Talk1_10", "Talk1_2", "Talk2_1", "Talk1_1", "Talk1_02"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["Talk1_1", "Talk1_2", "Talk1_02", "Talk1_10", "Talk2_1"]
+        );
+    }
+}
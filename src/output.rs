@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, FileTimes},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// how `PathSets::rename()` puts the audio files into the output folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+    /// 元のファイルを移動する。別のファイルシステムへはコピーしてから元のファイルを削除する
+    #[default]
+    Move,
+    /// 元のファイルを残してコピーする。タイムスタンプを保持し、コピー後にサイズを確認する
+    Copy,
+    Hardlink,
+    /// 元のファイルの絶対パスへのシンボリックリンクを作る
+    Symlink,
+}
+
+/// what to do when the output folder already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExistingDirPolicy {
+    /// 既存のフォルダにそのまま出力する。既にあるファイルは重複として扱う
+    Merge,
+    /// エラーにする
+    #[default]
+    Fail,
+    /// `renamed_1`, `renamed_2`...のうち存在しないフォルダを作って出力する
+    Fresh,
+}
+
+/// return the folder to output to according to `policy`.
+/// `Fresh`以外では`dir`をそのまま返す
+pub(crate) fn resolve_dir<P: AsRef<Path>>(dir: P, policy: ExistingDirPolicy) -> PathBuf {
+    let dir = dir.as_ref();
+    if policy != ExistingDirPolicy::Fresh || !dir.exists() {
+        return dir.to_path_buf();
+    }
+    let name = dir
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+    (1..)
+        .map(|n| dir.with_file_name(format!("{}_{}", name, n)))
+        .find(|p| !p.exists())
+        .unwrap_or_default()
+}
+
+/// put `from` to `to` according to `mode`.
+pub(crate) fn apply<P: AsRef<Path>, Q: AsRef<Path>>(
+    mode: OutputMode,
+    from: P,
+    to: Q,
+) -> io::Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    match mode {
+        OutputMode::Move => match fs::rename(from, to) {
+            // 別のファイルシステムには移動できないため、コピーしてから削除する
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                copy(from, to)?;
+                fs::remove_file(from)
+            }
+            r => r,
+        },
+        OutputMode::Copy => copy(from, to),
+        OutputMode::Hardlink => fs::hard_link(from, to),
+        OutputMode::Symlink => symlink(&fs::canonicalize(from)?, to),
+    }
+}
+
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    if to.exists() {
+        // fs::copyは上書きしてしまうため
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "destination already exists",
+        ));
+    }
+    let copied = fs::copy(from, to)?;
+    let metadata = fs::metadata(from)?;
+    if copied != metadata.len() || fs::metadata(to)?.len() != metadata.len() {
+        let _ = fs::remove_file(to);
+        return Err(io::Error::other("copied file size does not match"));
+    }
+
+    let times = FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    fs::File::options().write(true).open(to)?.set_times(times)
+}
+
+#[cfg(unix)]
+fn symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(from, to)
+}
+
+#[cfg(windows)]
+fn symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::ready_in;
+
+    #[test]
+    fn modes() {
+        let dir = ready_in("output_modes");
+        let from = dir.join("Talk1_1.wav");
+        for (mode, name) in [
+            (OutputMode::Copy, "copy.wav"),
+            (OutputMode::Hardlink, "hardlink.wav"),
+            (OutputMode::Symlink, "symlink.wav"),
+        ] {
+            apply(mode, &from, dir.join(name)).unwrap();
+            assert_eq!(fs::read(&from).unwrap(), fs::read(dir.join(name)).unwrap());
+        }
+        assert!(from.exists());
+        assert_eq!(
+            fs::metadata(&from).unwrap().modified().unwrap(),
+            fs::metadata(dir.join("copy.wav"))
+                .unwrap()
+                .modified()
+                .unwrap()
+        );
+        assert!(apply(OutputMode::Copy, &from, dir.join("copy.wav")).is_err());
+
+        apply(OutputMode::Move, &from, dir.join("move.wav")).unwrap();
+        assert!(!from.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn move_across_devices() {
+        // tmpfsが無い環境では確認できない
+        let other = Path::new("/dev/shm");
+        if !other.is_dir() {
+            return;
+        }
+        let dir = ready_in("move_across_devices");
+        let from = dir.join("Talk1_1.wav");
+        let bytes = fs::read(&from).unwrap();
+        let to = other.join("track2line_move_across_devices.wav");
+        apply(OutputMode::Move, &from, &to).unwrap();
+        assert!(!from.exists());
+        assert_eq!(fs::read(&to).unwrap(), bytes);
+        fs::remove_file(to).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn fresh_dir() {
+        let dir = ready_in("fresh_dir");
+        let out = dir.join("renamed");
+        assert_eq!(resolve_dir(&out, ExistingDirPolicy::Fresh), out);
+        fs::create_dir(&out).unwrap();
+        fs::create_dir(dir.join("renamed_1")).unwrap();
+        assert_eq!(resolve_dir(&out, ExistingDirPolicy::Merge), out);
+        assert_eq!(
+            resolve_dir(&out, ExistingDirPolicy::Fresh),
+            dir.join("renamed_2")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
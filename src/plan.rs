@@ -0,0 +1,200 @@
+use crate::{Error, Operation};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, path::PathBuf};
+
+/// state of the line of one entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineStatus {
+    #[default]
+    Ok,
+    /// テキストファイルが無かった
+    Missing,
+    /// テキストファイルはあったが、セリフが空だった
+    Empty,
+}
+
+/// where the line of one entry came from. `PathSets::set_line_sources()`で順番を指定する
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineSource {
+    /// 同名のテキストファイル
+    Text,
+    /// 音声ファイルの名前(`FilenameParser`)
+    Filename,
+    /// 音声認識。`experimental` featureが無い場合は使わない
+    Transcription,
+    /// `PathSets::set_script()`で指定した台本
+    Script,
+    /// どこからも取れなかった場合の`empty_N`
+    #[default]
+    Placeholder,
+}
+
+/// one entry of the rename plan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanRow {
+    pub source: PathBuf,
+    pub target: Option<PathBuf>,
+    /// 切る前のセリフ。`Missing`と`Empty`の場合は空
+    pub line: String,
+    pub status: LineStatus,
+    /// 古い計画には無いため省略可能
+    #[serde(default)]
+    pub line_source: LineSource,
+}
+
+/// rename plan made by `PathSets::plan()`, for the GUI and review tools.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    pub rows: Vec<PlanRow>,
+}
+impl Plan {
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(Error::serialize)
+    }
+
+    /// 1行目はヘッダ(`source,target,line,status,line_source`)
+    pub fn to_csv(&self) -> Result<String, Error> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        for i in &self.rows {
+            writer.serialize(i).map_err(Error::serialize)?;
+        }
+        let bytes = writer
+            .into_inner()
+            .map_err(|e| Error::serialize(e.into_error()))?;
+        String::from_utf8(bytes).map_err(Error::serialize)
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, Error> {
+        serde_json::from_str(content).map_err(|e| Error::InvalidPlan(vec![e.to_string()]))
+    }
+
+    /// `to_csv()`と同じ形式。`target`が空の行はrenameしない
+    pub fn from_csv(content: &str) -> Result<Self, Error> {
+        let mut reader = csv::Reader::from_reader(content.as_bytes());
+        let rows = reader
+            .deserialize()
+            .collect::<Result<Vec<PlanRow>, _>>()
+            .map_err(|e| Error::InvalidPlan(vec![e.to_string()]))?;
+        Ok(Self { rows })
+    }
+
+    pub fn load_json<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_json(&fs::read_to_string(&path).map_err(Error::io(Operation::Read, &path))?)
+    }
+
+    pub fn load_csv<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_csv(&fs::read_to_string(&path).map_err(Error::io(Operation::Read, &path))?)
+    }
+
+    /// ファイルを上書きで保存する
+    pub fn save_json<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let content = self.to_json().map_err(|e| e.with_path(&path))?;
+        fs::write(&path, content).map_err(Error::io(Operation::Write, &path))
+    }
+
+    /// ファイルを上書きで保存する
+    pub fn save_csv<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let content = self.to_csv().map_err(|e| e.with_path(&path))?;
+        fs::write(&path, content).map_err(Error::io(Operation::Write, &path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::ready_in, PathSets};
+    use std::fs;
+
+    #[test]
+    fn export() {
+        let dir = ready_in("plan_export");
+        let plan = PathSets::new(&dir, "wav", "txt").unwrap().plan();
+        let json = plan.to_json().unwrap();
+        let csv = plan.to_csv().unwrap();
+        println!("{}\n{}", json, csv);
+
+        assert!(json.contains("\"status\": \"empty\""));
+        assert!(json.contains("\"line_source\": \"filename\""));
+        assert!(csv.starts_with("source,target,line,status,line_source\n"));
+        assert_eq!(csv.lines().count(), plan.rows.len() + 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn import_edited() {
+        let dir = ready_in("plan_import");
+        let plan = PathSets::new(&dir, "wav", "txt").unwrap().plan();
+        let mut edited = Plan::from_csv(&plan.to_csv().unwrap()).unwrap();
+        assert_eq!(plan, edited);
+
+        // 手で直した場合
+        edited.rows[0].target = Some(dir.join("edited").join("手で直した名前.wav"));
+        edited.rows[1].target = None;
+        let mut sets = PathSets::from_plan(&edited).unwrap();
+        let report = sets.rename().unwrap();
+        println!("{}", report);
+        assert!(report.is_ok());
+        assert_eq!(report.summary().skipped, 1);
+        assert!(dir.join("edited").join("手で直した名前.wav").exists());
+        assert!(edited.rows[1].source.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn import_relative_target() {
+        let dir = ready_in("plan_relative");
+        let mut plan = PathSets::new(&dir, "wav", "txt").unwrap().plan();
+        plan.rows.truncate(1);
+        plan.rows[0].target = Some(PathBuf::from("edited_name.wav"));
+        let mut sets = PathSets::from_plan(&plan).unwrap();
+        // 元のファイルのフォルダからのパス
+        assert_eq!(sets.output_dir(), dir);
+        assert!(sets.rename().unwrap().is_ok());
+        assert!(dir.join("edited_name.wav").exists());
+        assert!(!PathBuf::from("edited_name.wav").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn empty_plan() {
+        assert!(matches!(
+            PathSets::from_plan(&Plan::default()),
+            Err(Error::InvalidPlan(_))
+        ));
+
+        // 変更後のパスが1つも無い場合は何も作らない
+        let dir = ready_in("plan_no_target");
+        let mut plan = PathSets::new(&dir, "wav", "txt").unwrap().plan();
+        for row in &mut plan.rows {
+            row.target = None;
+        }
+        let mut sets = PathSets::from_plan(&plan).unwrap();
+        let report = sets.rename().unwrap();
+        assert_eq!(report.summary().skipped, plan.rows.len());
+        assert!(!sets.output_dir().exists());
+        assert!(!sets.journal_path().exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_plan() {
+        let dir = ready_in("plan_invalid");
+        let mut plan = PathSets::new(&dir, "wav", "txt").unwrap().plan();
+        plan.rows[0].target = Some(dir.join("a?.wav"));
+        plan.rows[1].target = Some(dir.join("same.wav"));
+        plan.rows[2].target = Some(dir.join("SAME.wav"));
+        plan.rows[3].source = dir.join("not_found.wav");
+        plan.rows[4].target = Some(dir.join("Talk1_1.wav"));
+
+        match PathSets::from_plan(&Plan::from_json(&plan.to_json().unwrap()).unwrap()) {
+            Err(Error::InvalidPlan(problems)) => {
+                println!("{:?}", problems);
+                assert_eq!(problems.len(), 4);
+            }
+            _ => panic!(),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
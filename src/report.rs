@@ -0,0 +1,122 @@
+use crate::Error;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// what happened to one entry in `PathSets::rename()`.
+#[derive(Debug)]
+pub enum RenameOutcome {
+    Renamed,
+    /// 変更後のパスが無かったため何もしなかった
+    Skipped,
+    /// 変更後のパスに既にファイルがあったため何もしなかった
+    Collided,
+    /// 失敗した操作(`Operation::Rename`など)とパスを持つ
+    Failed(Error),
+}
+
+#[derive(Debug)]
+pub struct RenameEntry {
+    pub from: PathBuf,
+    pub to: Option<PathBuf>,
+    pub outcome: RenameOutcome,
+    /// 音声ファイルと一緒に出力したファイル(セリフのファイルなど)の結果
+    pub sidecars: Vec<RenameEntry>,
+}
+impl RenameEntry {
+    pub(crate) fn new<P: AsRef<Path>, Q: AsRef<Path>>(
+        from: P,
+        to: Option<Q>,
+        outcome: RenameOutcome,
+    ) -> Self {
+        Self {
+            from: from.as_ref().to_path_buf(),
+            to: to.map(|p| p.as_ref().to_path_buf()),
+            outcome,
+            sidecars: Vec::new(),
+        }
+    }
+
+    fn is_ok(&self) -> bool {
+        matches!(
+            self.outcome,
+            RenameOutcome::Renamed | RenameOutcome::Skipped
+        ) && self.sidecars.iter().all(|i| i.is_ok())
+    }
+}
+
+/// counts of each outcome of the audio files, for showing in the front-ends.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RenameSummary {
+    pub renamed: usize,
+    pub skipped: usize,
+    pub collided: usize,
+    pub failed: usize,
+}
+impl fmt::Display for RenameSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "renamed: {}, skipped: {}, collided: {}, failed: {}",
+            self.renamed, self.skipped, self.collided, self.failed
+        )
+    }
+}
+
+/// result of `PathSets::rename()`. one entry per audio file.
+#[derive(Debug, Default)]
+pub struct RenameReport {
+    pub entries: Vec<RenameEntry>,
+    /// 途中でjournalを保存できなかった場合の最初のエラー。`undo()`で戻せないファイルがある
+    pub journal_error: Option<Error>,
+}
+impl RenameReport {
+    pub(crate) fn push(&mut self, entry: RenameEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn summary(&self) -> RenameSummary {
+        let mut summary = RenameSummary::default();
+        for i in &self.entries {
+            match i.outcome {
+                RenameOutcome::Renamed => summary.renamed += 1,
+                RenameOutcome::Skipped => summary.skipped += 1,
+                RenameOutcome::Collided => summary.collided += 1,
+                RenameOutcome::Failed(_) => summary.failed += 1,
+            }
+        }
+        summary
+    }
+
+    /// 一緒に出力したファイルを含め、全て成功またはスキップされた場合true
+    pub fn is_ok(&self) -> bool {
+        self.journal_error.is_none() && self.entries.iter().all(|i| i.is_ok())
+    }
+}
+impl fmt::Display for RenameReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.summary())?;
+        if let Some(e) = &self.journal_error {
+            writeln!(f, "! failed to save the journal: {}", e)?;
+        }
+        for i in self
+            .entries
+            .iter()
+            .flat_map(|i| [i].into_iter().chain(&i.sidecars))
+        {
+            let to =
+                i.to.as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or("None".to_string());
+            match &i.outcome {
+                RenameOutcome::Collided => {
+                    writeln!(f, "! collided: {} -x-> {}", i.from.display(), to)?
+                }
+                RenameOutcome::Failed(e) => writeln!(f, "! failed: {}: {}", i.from.display(), e)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+/// Linux, macOS, Windowsのいずれかでファイル名に使えない文字
+const INVALID_CHARS: [char; 9] = ['/', '\\', ':', '?', '*', '"', '<', '>', '|'];
+
+/// Windowsの予約名。拡張子が付いていても使えない
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// make a string into one valid file name on Linux, macOS and Windows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sanitizer {
+    table: Vec<(char, String)>,
+    replacement: String,
+    fullwidth: bool,
+}
+impl Default for Sanitizer {
+    /// 使えない文字は"_"に置き換え、全角への置き換えはしない
+    fn default() -> Self {
+        Self {
+            table: Vec::new(),
+            replacement: "_".to_string(),
+            fullwidth: false,
+        }
+    }
+}
+impl Sanitizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 使えない文字を置き換える文字列を設定
+    pub fn set_replacement(&mut self, replacement: &str) {
+        self.replacement = replacement.to_string();
+    }
+
+    /// 使えない文字を全角の似た文字(`?`→`？`など)に置き換えるか
+    pub fn set_fullwidth(&mut self, fullwidth: bool) {
+        self.fullwidth = fullwidth;
+    }
+
+    /// add a replacement to the table. the table is applied before the other rules.
+    pub fn insert(&mut self, from: char, to: &str) {
+        match self.table.iter_mut().find(|(c, _)| *c == from) {
+            Some(v) => v.1 = to.to_string(),
+            None => self.table.push((from, to.to_string())),
+        }
+    }
+
+    /// return a valid file name made from `name`.
+    pub fn sanitize(&self, name: &str) -> String {
+        let replaced = name
+            .chars()
+            .map(|c| match self.table.iter().find(|(from, _)| *from == c) {
+                Some((_, to)) => to.clone(),
+                None => c.to_string(),
+            })
+            .collect::<String>();
+
+        let mut tmp = String::new();
+        for c in replaced.chars() {
+            if INVALID_CHARS.contains(&c) {
+                match fullwidth_of(c).filter(|_| self.fullwidth) {
+                    Some(v) => tmp.push(v),
+                    None => tmp.push_str(&self.replacement),
+                }
+            } else if c == '\n' || c == '\r' || c == '\t' {
+                tmp.push(' ');
+            } else if !c.is_control() {
+                tmp.push(c);
+            }
+        }
+
+        // Windowsでは末尾のドットとスペースは消されてしまう
+        let mut tmp = tmp
+            .trim_start()
+            .trim_end_matches(|c: char| c == '.' || c.is_whitespace())
+            .to_string();
+
+        if tmp.is_empty() || tmp.chars().all(|c| c == '.') {
+            return "_".to_string();
+        }
+
+        let stem = tmp.split('.').next().unwrap_or_default().trim_end();
+        if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+            tmp.insert(stem.len(), '_');
+        }
+        tmp
+    }
+}
+
+/// 使えない文字に対応する全角の文字
+fn fullwidth_of(c: char) -> Option<char> {
+    match c {
+        '/' => Some('／'),
+        '\\' => Some('＼'),
+        ':' => Some('：'),
+        '?' => Some('？'),
+        '*' => Some('＊'),
+        '"' => Some('＂'),
+        '<' => Some('＜'),
+        '>' => Some('＞'),
+        '|' => Some('｜'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_chars() {
+        let s = Sanitizer::default();
+        assert_eq!(s.sanitize("a/b\\c:d?e*f\"g<h>i|j"), "a_b_c_d_e_f_g_h_i_j");
+        assert_eq!(s.sanitize("一行目\n二行目\u{7}"), "一行目 二行目");
+        assert_eq!(s.sanitize("end... "), "end");
+        assert_eq!(s.sanitize(".."), "_");
+        assert_eq!(s.sanitize("  "), "_");
+    }
+
+    #[test]
+    fn reserved_names() {
+        let s = Sanitizer::default();
+        assert_eq!(s.sanitize("con"), "con_");
+        assert_eq!(s.sanitize("NUL.txt"), "NUL_.txt");
+        assert_eq!(s.sanitize("CONSOLE"), "CONSOLE");
+    }
+
+    #[test]
+    fn fullwidth_and_table() {
+        let mut s = Sanitizer::new();
+        s.set_fullwidth(true);
+        assert_eq!(s.sanitize("本当?"), "本当？");
+        s.insert(' ', "-");
+        s.insert('?', "/");
+        assert_eq!(s.sanitize("a b?"), "a-b／");
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{encoding, Error, Operation};
+use std::{fmt, fs, path::Path};
+
+/// format of a script file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScriptFormat {
+    /// 1行に1つのセリフ
+    #[default]
+    Plain,
+    /// `speaker,line`または`line`。話者の列は省略可能
+    Csv,
+    /// `speaker\tline`または`line`
+    Tsv,
+}
+impl ScriptFormat {
+    /// `.csv`と`.tsv`以外は`Plain`
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let ext = path
+            .as_ref()
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "csv" => Self::Csv,
+            "tsv" => Self::Tsv,
+            _ => Self::Plain,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptLine {
+    pub speaker: Option<String>,
+    pub line: String,
+}
+
+/// one script file for all audio files (`Talk1_1.wav` ... `Talk1_N.wav`).
+/// N番目のセリフを自然順でN番目の音声ファイルに対応させる。空の行は読み飛ばす
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    pub lines: Vec<ScriptLine>,
+}
+impl Script {
+    /// CSVとTSVで1行目が`speaker`, `line`の場合はヘッダとして読み飛ばす
+    pub fn parse(content: &str, format: ScriptFormat) -> Result<Self, Error> {
+        let delimiter = match format {
+            ScriptFormat::Plain => {
+                let lines = content
+                    .lines()
+                    .map(|l| l.trim())
+                    .filter(|l| !l.is_empty())
+                    .map(|l| ScriptLine {
+                        speaker: None,
+                        line: l.to_string(),
+                    })
+                    .collect();
+                return Ok(Self { lines });
+            }
+            ScriptFormat::Csv => b',',
+            ScriptFormat::Tsv => b'\t',
+        };
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(delimiter)
+            .from_reader(content.as_bytes());
+        let mut lines = Vec::new();
+        for (n, record) in reader.records().enumerate() {
+            let record = record.map_err(|e| Error::InvalidScript(e.to_string()))?;
+            let fields = record.iter().map(|f| f.trim()).collect::<Vec<_>>();
+            let is_header = |v: &[&str]| {
+                v.iter()
+                    .all(|f| f.eq_ignore_ascii_case("speaker") || f.eq_ignore_ascii_case("line"))
+            };
+            if n == 0 && is_header(&fields) {
+                continue;
+            }
+            let (speaker, line) = match fields.as_slice() {
+                [] => continue,
+                [line] => (None, *line),
+                [speaker, line] => (Some(speaker.to_string()).filter(|s| !s.is_empty()), *line),
+                _ => {
+                    return Err(Error::InvalidScript(format!(
+                        "too many columns in row {}",
+                        n + 1
+                    )))
+                }
+            };
+            if !line.is_empty() {
+                lines.push(ScriptLine {
+                    speaker,
+                    line: line.to_string(),
+                });
+            }
+        }
+        Ok(Self { lines })
+    }
+
+    /// 拡張子から形式を決めて読む。文字コードはセリフのファイルと同じく判定する
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let bytes = fs::read(&path).map_err(Error::io(Operation::Read, &path))?;
+        Self::parse(
+            &encoding::decode(&bytes, encoding::default_fallback()),
+            ScriptFormat::from_path(&path),
+        )
+    }
+}
+
+/// the number of the script lines and the audio files differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptMismatch {
+    pub lines: usize,
+    pub audio: usize,
+}
+impl fmt::Display for ScriptMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "script has {} lines but there are {} audio files",
+            self.lines, self.audio
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        let plain = Script::parse("こんにちは\n\n  さようなら \n", ScriptFormat::Plain).unwrap();
+        assert_eq!(plain.lines.len(), 2);
+        assert_eq!(plain.lines[1].line, "さようなら");
+
+        let csv = Script::parse(
+            "speaker,line\nずんだもん,\"こんにちは、なのだ\"\n,名無し\nセリフだけ\n",
+            ScriptFormat::Csv,
+        )
+        .unwrap();
+        assert_eq!(csv.lines.len(), 3);
+        assert_eq!(csv.lines[0].speaker.as_deref(), Some("ずんだもん"));
+        assert_eq!(csv.lines[0].line, "こんにちは、なのだ");
+        assert_eq!(csv.lines[1].speaker, None);
+        assert_eq!(csv.lines[2].line, "セリフだけ");
+
+        let tsv = Script::parse("つくよみちゃん\tこんにちは\n", ScriptFormat::Tsv).unwrap();
+        assert_eq!(tsv.lines[0].speaker.as_deref(), Some("つくよみちゃん"));
+        assert!(Script::parse("a,b,c\n", ScriptFormat::Csv).is_err());
+    }
+}
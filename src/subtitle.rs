@@ -0,0 +1,213 @@
+use crate::{timeline::Timeline, Error, Operation};
+use std::{fmt::Write, fs, path::Path, time::Duration};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// 行頭に置かない文字(禁則処理)
+const NO_LINE_START: &str =
+    "、。，．・：；？！ー…‥）」』】〕〉》”’ぁぃぅぇぉっゃゅょゎァィゥェォッャュョヮヵヶ々,.!?:;)]}";
+
+/// SRT and WebVTT writer for a `Timeline`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Subtitles {
+    wrap_width: Option<usize>,
+}
+impl Subtitles {
+    /// 1行の表示幅(全角は2)。`None`(デフォルト)の場合は折り返さない
+    pub fn set_wrap_width(&mut self, width: Option<usize>) {
+        self.wrap_width = width;
+    }
+
+    /// セリフが無いクリップは字幕にしない
+    pub fn to_srt(&self, timeline: &Timeline) -> String {
+        let mut out = String::new();
+        for (n, (start, end, _, text)) in self.cues(timeline).into_iter().enumerate() {
+            let _ = write!(
+                out,
+                "{}\n{} --> {}\n{}\n\n",
+                n + 1,
+                timestamp(start, ','),
+                timestamp(end, ','),
+                text
+            );
+        }
+        out
+    }
+
+    /// 話者がある場合は`<v 話者>`を付ける。`&`, `<`, `>`は文字参照にする(`-->`も含む)
+    pub fn to_vtt(&self, timeline: &Timeline) -> String {
+        let mut out = String::from("WEBVTT\n\n");
+        for (start, end, speaker, text) in self.cues(timeline) {
+            let voice = speaker
+                .map(|s| format!("<v {}>", vtt_escape(s)))
+                .unwrap_or_default();
+            let _ = write!(
+                out,
+                "{} --> {}\n{}{}\n\n",
+                timestamp(start, '.'),
+                timestamp(end, '.'),
+                voice,
+                vtt_escape(&text)
+            );
+        }
+        out
+    }
+
+    /// ファイルを上書きで保存する
+    pub fn save_srt<P: AsRef<Path>>(&self, timeline: &Timeline, path: P) -> Result<(), Error> {
+        fs::write(&path, self.to_srt(timeline)).map_err(Error::io(Operation::Write, &path))
+    }
+
+    /// ファイルを上書きで保存する
+    pub fn save_vtt<P: AsRef<Path>>(&self, timeline: &Timeline, path: P) -> Result<(), Error> {
+        fs::write(&path, self.to_vtt(timeline)).map_err(Error::io(Operation::Write, &path))
+    }
+
+    /// (開始, 終了, 話者, 折り返したセリフ)。空行はキューの終わりになるため詰める
+    fn cues<'a>(
+        &self,
+        timeline: &'a Timeline,
+    ) -> Vec<(Duration, Duration, Option<&'a str>, String)> {
+        timeline
+            .clips
+            .iter()
+            .filter(|c| !c.line.trim().is_empty())
+            .map(|c| {
+                let text = match self.wrap_width {
+                    Some(width) => wrap(&c.line, width).join("\n"),
+                    None => c
+                        .line
+                        .lines()
+                        .map(str::trim)
+                        .filter(|l| !l.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                };
+                (c.start, c.end(), c.speaker.as_deref(), text)
+            })
+            .collect()
+    }
+}
+
+/// WebVTTのキューの中では`&`, `<`, `>`をそのまま書けないため
+fn vtt_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// `00:00:01,500`(SRT)または`00:00:01.500`(WebVTT)
+fn timestamp(t: Duration, separator: char) -> String {
+    let ms = t.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
+
+/// wrap `text` so that each line is at most `width` columns (全角は2列).
+/// 日本語などは文字の間で、英語などは空白で折り返す。句読点や閉じ括弧は行頭に置かない
+pub(crate) fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.trim().lines() {
+        let mut line = String::new();
+        for g in paragraph.trim().graphemes(true) {
+            if line.width() + g.width() <= width || line.is_empty() {
+                line.push_str(g);
+                continue;
+            }
+            if NO_LINE_START.contains(g) {
+                // 前の行にぶら下げる
+                line.push_str(g);
+                continue;
+            }
+            let in_word = |s: &str| s.chars().all(|c| c.is_ascii_alphanumeric());
+            let last = line.graphemes(true).next_back().unwrap_or_default();
+            let rest = match line.rfind(' ') {
+                Some(i) if in_word(g) && in_word(last) => line.split_off(i),
+                _ => String::new(),
+            };
+            lines.push(line.trim_end().to_string());
+            line = rest.trim_start().to_string();
+            if !(line.is_empty() && g.trim().is_empty()) {
+                line.push_str(g);
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeline::Clip;
+    use std::path::PathBuf;
+
+    #[test]
+    fn srt_and_vtt() {
+        let clip = |start: u64, line: &str| Clip {
+            source: PathBuf::from("a.wav"),
+            path: PathBuf::from("a.wav"),
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(1500),
+            line: line.to_string(),
+            speaker: None,
+        };
+        let timeline = Timeline {
+            clips: vec![
+                clip(0, "こんにちは"),
+                clip(2000, ""),
+                clip(3_723_004, "さようなら"),
+            ],
+        };
+        let subtitles = Subtitles::default();
+        assert_eq!(
+            subtitles.to_srt(&timeline),
+            "1\n00:00:00,000 --> 00:00:01,500\nこんにちは\n\n2\n01:02:03,004 --> 01:02:04,504\nさようなら\n\n"
+        );
+        assert!(subtitles
+            .to_vtt(&timeline)
+            .starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:01.500\nこんにちは\n"));
+    }
+
+    #[test]
+    fn blank_lines_and_escape() {
+        let timeline = Timeline {
+            clips: vec![Clip {
+                source: PathBuf::from("a.wav"),
+                path: PathBuf::from("a.wav"),
+                start: Duration::ZERO,
+                duration: Duration::from_millis(1500),
+                line: "一行目\n\n二行目 A<B & C --> D".to_string(),
+                speaker: Some("<話者>".to_string()),
+            }],
+        };
+        let mut subtitles = Subtitles::default();
+        for width in [None, Some(40)] {
+            subtitles.set_wrap_width(width);
+            assert!(subtitles
+                .to_srt(&timeline)
+                .ends_with("\n一行目\n二行目 A<B & C --> D\n\n"));
+            let vtt = subtitles.to_vtt(&timeline);
+            assert!(vtt.ends_with("\n<v &lt;話者&gt;>一行目\n二行目 A&lt;B &amp; C --&gt; D\n\n"));
+            assert_eq!(vtt.matches("-->").count(), 1);
+        }
+    }
+
+    #[test]
+    fn wrap_cjk() {
+        assert_eq!(
+            wrap("これはテストです。折り返します", 8),
+            vec!["これはテ", "ストです。", "折り返し", "ます"]
+        );
+        assert_eq!(wrap("hello world again", 11), vec!["hello world", "again"]);
+        assert_eq!(wrap("短い", 20), vec!["短い"]);
+    }
+}
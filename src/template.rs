@@ -0,0 +1,215 @@
+use crate::{truncate::MAX_FILE_NAME_BYTES, Error};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 使えるトークン一覧
+const TOKENS: [&str; 8] = [
+    "stem", "index", "number", "line", "speaker", "style", "ext", "date",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Token { name: String, width: Option<usize> },
+}
+
+/// naming template for the new file names, e.g. `{index:03}_{speaker}_{line}`.
+/// # Tokens
+/// * `{stem}` - original file name without extension.
+/// * `{index}` - sequence index starting at 1. `{index:03}` pads with zeros (up to 255).
+/// * `{number}` - number parsed from the file name, or the sequence index if none. `{number:03}` too.
+/// * `{line}` - line text.
+/// * `{speaker}` / `{style}` - speaker and style. empty if unknown.
+/// * `{ext}` - original extension.
+/// * `{date}` - today's date (`YYYYMMDD`, UTC).
+///
+/// `{{`と`}}`で`{`と`}`そのものを表す
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+impl Default for Template {
+    /// `{line}`で初期化
+    fn default() -> Self {
+        Self::parse("{line}").unwrap()
+    }
+}
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let err = |msg: String| Error::InvalidTemplate(format!("{} in \"{}\"", msg, source));
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => return Err(err("unmatched '}'".to_string())),
+                '{' => {
+                    let mut body = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some('{') | None => return Err(err("unclosed '{'".to_string())),
+                            Some(v) => body.push(v),
+                        }
+                    }
+                    let (name, spec) = match body.split_once(':') {
+                        Some((n, s)) => (n.trim(), Some(s.trim())),
+                        None => (body.trim(), None),
+                    };
+                    if !TOKENS.contains(&name) {
+                        return Err(err(format!("unknown token \"{}\"", name)));
+                    }
+                    let width = match spec {
+                        None => None,
+                        // ファイル名に収まらない幅は切られるだけなので受け付けない
+                        Some(s) if name == "index" || name == "number" => Some(
+                            s.parse::<usize>()
+                                .ok()
+                                .filter(|w| *w <= MAX_FILE_NAME_BYTES)
+                                .ok_or_else(|| err(format!("invalid width \"{}\"", s)))?,
+                        ),
+                        Some(_) => return Err(err(format!("\"{}\" takes no format", name))),
+                    };
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Token {
+                        name: name.to_string(),
+                        width,
+                    });
+                }
+                _ => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        if segments.is_empty() {
+            return Err(err("empty template".to_string()));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// build a name from the fields. sanitizeはしない
+    pub(crate) fn render(&self, fields: &Fields) -> String {
+        self.segments
+            .iter()
+            .map(|s| match s {
+                Segment::Text(t) => t.clone(),
+                Segment::Token { name, width } => match name.as_str() {
+                    "stem" => fields.stem.to_string(),
+                    "index" => format!("{:0width$}", fields.index, width = width.unwrap_or(0)),
+                    "number" => format!("{:0width$}", fields.number, width = width.unwrap_or(0)),
+                    "line" => fields.line.to_string(),
+                    "speaker" => fields.speaker.unwrap_or_default().to_string(),
+                    "style" => fields.style.unwrap_or_default().to_string(),
+                    "ext" => fields.ext.to_string(),
+                    "date" => fields.date.to_string(),
+                    _ => String::new(),
+                },
+            })
+            .collect()
+    }
+}
+
+/// values for the template tokens of one entry.
+pub(crate) struct Fields<'a> {
+    pub stem: &'a str,
+    pub index: usize,
+    pub number: usize,
+    pub line: &'a str,
+    pub speaker: Option<&'a str>,
+    pub style: Option<&'a str>,
+    pub ext: &'a str,
+    pub date: &'a str,
+}
+
+/// 今日の日付(UTC)を`YYYYMMDD`で返す
+pub(crate) fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86400)
+        .unwrap_or_default() as i64;
+    let (y, m, d) = civil_from_days(days);
+    format!("{:04}{:02}{:02}", y, m, d)
+}
+
+/// 1970-01-01からの日数を(年, 月, 日)に変換
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_tokens() {
+        let t = Template::parse("{index:03}_{speaker}_{line}{{{ext}}}").unwrap();
+        let fields = Fields {
+            stem: "Talk1_1",
+            index: 7,
+            number: 12,
+            line: "こんにちは",
+            speaker: Some("つくよみちゃん"),
+            style: None,
+            ext: "wav",
+            date: "20250327",
+        };
+        assert_eq!(t.render(&fields), "007_つくよみちゃん_こんにちは{wav}");
+        let t = Template::parse("{stem}__{line}_{style}{date}").unwrap();
+        assert_eq!(t.render(&fields), "Talk1_1__こんにちは_20250327");
+        let t = Template::parse("{number:02}_{index}").unwrap();
+        assert_eq!(t.render(&fields), "12_7");
+    }
+
+    #[test]
+    fn invalid_templates() {
+        for t in [
+            "",
+            "{line",
+            "line}",
+            "{foo}",
+            "{index:ab}",
+            "{line:3}",
+            "{index:256}",
+            "{number:70000}",
+        ] {
+            let e = Template::parse(t);
+            println!("{:?}", e);
+            assert!(matches!(e, Err(Error::InvalidTemplate(_))));
+        }
+    }
+
+    #[test]
+    fn date() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(20174), (2025, 3, 27));
+    }
+}
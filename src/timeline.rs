@@ -0,0 +1,55 @@
+use std::{path::PathBuf, time::Duration};
+
+/// one audio clip placed on the timeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clip {
+    /// 元のパス
+    pub source: PathBuf,
+    /// rename後のパス。変更後のパスが無い場合は元のパス
+    pub path: PathBuf,
+    pub start: Duration,
+    pub duration: Duration,
+    /// 切る前のセリフ。セリフが無い場合は空
+    pub line: String,
+    pub speaker: Option<String>,
+}
+impl Clip {
+    pub fn end(&self) -> Duration {
+        self.start + self.duration
+    }
+}
+
+/// clips laid out in the order of `PathSets` with a gap, made by `PathSets::timeline()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timeline {
+    pub clips: Vec<Clip>,
+}
+impl Timeline {
+    /// 最後のクリップの終わり
+    pub fn duration(&self) -> Duration {
+        self.clips.last().map(|c| c.end()).unwrap_or_default()
+    }
+
+    /// 話者ごとのトラック(0から、最初に出てきた順)。話者が無いクリップは0
+    pub(crate) fn speaker_tracks(&self) -> Vec<usize> {
+        let mut speakers = Vec::<&str>::new();
+        self.clips
+            .iter()
+            .map(|c| match &c.speaker {
+                Some(s) => match speakers.iter().position(|v| v == s) {
+                    Some(i) => i,
+                    None => {
+                        speakers.push(s);
+                        speakers.len() - 1
+                    }
+                },
+                None => 0,
+            })
+            .collect()
+    }
+}
+
+/// `t`をフレーム数に直す(四捨五入)
+pub(crate) fn frames(t: Duration, fps: u32) -> u64 {
+    (t.as_secs_f64() * fps as f64).round() as u64
+}
@@ -1,21 +1,26 @@
+use crate::{Error, Operation};
 use rodio::Decoder;
-use std::{fs, io::BufReader, path::Path};
+use std::{fmt::Debug, fs, io::BufReader, path::Path};
 use whisper_rs::{self, WhisperContext, WhisperContextParameters};
 
 /// langがNoneの場合は"ja"になります。
 /// 指定された音声ファイルを音声認識し、テキストに変換します。
 /// この関数は正しい結果を返さないため、実験的な機能です。
+/// モデルや音声ファイルを読めない場合は`Error::Transcription`
 #[cfg(feature = "experimental")]
 pub fn transcription<MP: AsRef<Path>, AP: AsRef<Path>>(
     model_path: MP,
     audio_path: AP,
     lang: Option<&str>,
-) -> String {
+) -> Result<String, Error> {
+    let audio_path = audio_path.as_ref();
+    let err = |e: &dyn Debug| Error::Transcription(format!("{}: {:?}", audio_path.display(), e));
+    let model_path = model_path.as_ref();
     let ctx = WhisperContext::new_with_params(
-        model_path.as_ref().to_str().unwrap(),
+        &model_path.to_string_lossy(),
         WhisperContextParameters::default(),
     )
-    .unwrap();
+    .map_err(|e| err(&e))?;
 
     let mut params =
         whisper_rs::FullParams::new(whisper_rs::SamplingStrategy::Greedy { best_of: 1 });
@@ -26,22 +31,22 @@ pub fn transcription<MP: AsRef<Path>, AP: AsRef<Path>>(
     params.set_print_realtime(false);
     params.set_print_timestamps(false);
 
-    let audio_file = fs::File::open(audio_path).unwrap();
-    let audio_data = Decoder::new(BufReader::new(audio_file)).unwrap();
+    let audio_file = fs::File::open(audio_path).map_err(Error::io(Operation::Read, audio_path))?;
+    let audio_data = Decoder::new(BufReader::new(audio_file)).map_err(|e| err(&e))?;
     // let rate = audio_data.sample_rate();
     // println!("original rate: {}", rate);
 
     let data = audio_data.into_iter().collect::<Vec<i16>>();
     let mut output = vec![0.0f32; data.len()];
 
-    whisper_rs::convert_integer_to_float_audio(&data, &mut output).unwrap();
+    whisper_rs::convert_integer_to_float_audio(&data, &mut output).map_err(|e| err(&e))?;
     // なんかファイルによってエラーになる`value: HalfSampleMissing(113767)`
-    let mono_data = whisper_rs::convert_stereo_to_mono_audio(&output).unwrap();
+    let mono_data = whisper_rs::convert_stereo_to_mono_audio(&output).map_err(|e| err(&e))?;
 
-    let mut state = ctx.create_state().unwrap();
-    state.full(params, &mono_data).unwrap();
+    let mut state = ctx.create_state().map_err(|e| err(&e))?;
+    state.full(params, &mono_data).map_err(|e| err(&e))?;
 
-    let num_segments = state.full_n_segments().unwrap();
+    let num_segments = state.full_n_segments().map_err(|e| err(&e))?;
 
     let mut sc = Vec::new();
 
@@ -50,10 +55,10 @@ pub fn transcription<MP: AsRef<Path>, AP: AsRef<Path>>(
         // let start_time = state.full_get_segment_t0(i).unwrap();
         // let end_time = state.full_get_segment_t1(i).unwrap();
         // println!("[{} - {}]: {}", start_time, end_time, segment);
-        sc.push(state.full_get_segment_text(i).unwrap());
+        sc.push(state.full_get_segment_text(i).map_err(|e| err(&e))?);
     }
 
-    concat_vec_to_string(sc)
+    Ok(concat_vec_to_string(sc))
     // println!("{:?}", state);
 }
 
@@ -0,0 +1,148 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// ファイル名の最大バイト数(多くのファイルシステムで255バイト)
+pub(crate) const MAX_FILE_NAME_BYTES: usize = 255;
+
+/// 区切りとして扱う句読点
+const PUNCTUATIONS: [char; 4] = ['。', '、', '！', '？'];
+
+/// unit of `Truncation::length`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TruncateUnit {
+    #[default]
+    Chars,
+    Graphemes,
+    Bytes,
+}
+impl TruncateUnit {
+    fn measure(&self, s: &str) -> usize {
+        match self {
+            TruncateUnit::Chars => s.chars().count(),
+            TruncateUnit::Graphemes => s.graphemes(true).count(),
+            TruncateUnit::Bytes => s.len(),
+        }
+    }
+}
+
+/// how to cut a line for the new file name and the preview of `check()`.
+/// 結合文字や絵文字の途中では切らない
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Truncation {
+    unit: TruncateUnit,
+    length: usize,
+    cut_at_punctuation: bool,
+    ellipsis: Option<String>,
+}
+impl Default for Truncation {
+    /// 20文字で切る
+    fn default() -> Self {
+        Self::new(TruncateUnit::Chars, 20)
+    }
+}
+impl Truncation {
+    pub fn new(unit: TruncateUnit, length: usize) -> Self {
+        Self {
+            unit,
+            length,
+            cut_at_punctuation: false,
+            ellipsis: None,
+        }
+    }
+
+    /// 切った後に最後の`。`, `、`, `！`, `？`の直後で切り直すか
+    pub fn set_cut_at_punctuation(&mut self, cut: bool) {
+        self.cut_at_punctuation = cut;
+    }
+
+    /// 切った場合に末尾に付ける文字列(`…`など)。長さに含まれる
+    pub fn set_ellipsis(&mut self, ellipsis: Option<&str>) {
+        self.ellipsis = ellipsis.map(|e| e.to_string());
+    }
+
+    /// 長さの上限
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// `text`の長さ。単位は`TruncateUnit`
+    pub fn measure(&self, text: &str) -> usize {
+        self.unit.measure(text.trim())
+    }
+
+    /// return `text` cut to the length.
+    pub fn truncate(&self, text: &str) -> String {
+        let text = text.trim();
+        if self.unit.measure(text) <= self.length {
+            return text.to_string();
+        }
+
+        let ellipsis = self.ellipsis.as_deref().unwrap_or_default();
+        let limit = self.length.saturating_sub(self.unit.measure(ellipsis));
+        let mut tmp = String::new();
+        let mut count = 0;
+        for g in text.graphemes(true) {
+            count += self.unit.measure(g);
+            if count > limit {
+                break;
+            }
+            tmp.push_str(g);
+        }
+
+        if self.cut_at_punctuation {
+            if let Some((i, c)) = tmp.char_indices().rfind(|(_, c)| PUNCTUATIONS.contains(c)) {
+                tmp.truncate(i + c.len_utf8());
+            }
+        }
+
+        format!("{}{}", tmp.trim_end(), ellipsis)
+    }
+}
+
+/// cut `text` to `max` bytes without breaking a grapheme.
+pub(crate) fn limit_bytes(text: &str, max: usize) -> String {
+    let mut tmp = String::new();
+    for g in text.graphemes(true) {
+        if tmp.len() + g.len() > max {
+            break;
+        }
+        tmp.push_str(g);
+    }
+    tmp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units() {
+        // "が"を"か"+結合濁点で表したもの
+        let text = "か\u{3099}か\u{3099}か\u{3099}";
+        let chars = Truncation::new(TruncateUnit::Chars, 3);
+        assert_eq!(chars.truncate(text), "か\u{3099}");
+        let graphemes = Truncation::new(TruncateUnit::Graphemes, 2);
+        assert_eq!(graphemes.truncate(text), "か\u{3099}か\u{3099}");
+        let bytes = Truncation::new(TruncateUnit::Bytes, 7);
+        assert_eq!(bytes.truncate("あいうえお"), "あい");
+        assert_eq!(Truncation::default().truncate("短い"), "短い");
+    }
+
+    #[test]
+    fn punctuation_and_ellipsis() {
+        let mut t = Truncation::new(TruncateUnit::Chars, 10);
+        t.set_cut_at_punctuation(true);
+        assert_eq!(
+            t.truncate("こんにちは、今日はいい天気ですね"),
+            "こんにちは、"
+        );
+        t.set_ellipsis(Some("…"));
+        assert_eq!(t.truncate("これはテストです"), "これはテストです");
+        assert_eq!(t.truncate("あいうえおかきくけこさ"), "あいうえおかきくけ…");
+    }
+
+    #[test]
+    fn limit() {
+        assert_eq!(limit_bytes("あいう", 8), "あい");
+        assert_eq!(limit_bytes("👨‍👩‍👧abc", 5), "");
+    }
+}
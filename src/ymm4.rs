@@ -0,0 +1,147 @@
+use crate::{
+    timeline::{frames, Timeline},
+    Error, Operation,
+};
+use serde_json::json;
+use std::{
+    fs,
+    path::{self, Path},
+};
+
+/// exporter of a `Timeline` as a YukkuriMovieMaker4 project (`.ymmp`).
+/// 音声アイテムとテキストアイテムのみのタイムラインを1つ持つ。
+/// 話者ごとに音声とテキストのレイヤーを1組ずつ使う(話者1は0, 1、話者2は2, 3...)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ymm4Export {
+    width: u32,
+    height: u32,
+    frame_rate: u32,
+    audio_rate: u32,
+}
+impl Default for Ymm4Export {
+    /// 1920x1080, 30fps, 48kHz
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            frame_rate: 30,
+            audio_rate: 48000,
+        }
+    }
+}
+impl Ymm4Export {
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// 0の場合は1にする
+    pub fn set_frame_rate(&mut self, fps: u32) {
+        self.frame_rate = fps.max(1);
+    }
+
+    pub fn set_audio_rate(&mut self, rate: u32) {
+        self.audio_rate = rate;
+    }
+
+    /// 音声ファイルのパスは絶対パスにする
+    pub fn to_ymmp(&self, timeline: &Timeline) -> Result<String, Error> {
+        let fps = self.frame_rate;
+        let mut items = Vec::new();
+        let mut length = 0;
+        let mut max_layer = 0;
+
+        for (c, track) in timeline.clips.iter().zip(timeline.speaker_tracks()) {
+            let frame = frames(c.start, fps);
+            let duration = frames(c.end(), fps).saturating_sub(frame).max(1);
+            let layer = track * 2;
+            length = length.max(frame + duration);
+            max_layer = max_layer.max(layer + 1);
+            // 相対パスはYMM4から解決できないため
+            let file = path::absolute(&c.path).unwrap_or(c.path.clone());
+
+            items.push(json!({
+                "$type": "YukkuriMovieMaker.Project.Items.AudioItem, YukkuriMovieMaker",
+                "FilePath": file.to_string_lossy(),
+                "Frame": frame,
+                "Layer": layer,
+                "Length": duration,
+                "PlaybackRate": 100.0,
+                "Remark": c.speaker.clone().unwrap_or_default(),
+            }));
+            if !c.line.trim().is_empty() {
+                items.push(json!({
+                    "$type": "YukkuriMovieMaker.Project.Items.TextItem, YukkuriMovieMaker",
+                    "Text": c.line.trim(),
+                    "Frame": frame,
+                    "Layer": layer + 1,
+                    "Length": duration,
+                    "Remark": c.speaker.clone().unwrap_or_default(),
+                }));
+            }
+        }
+
+        let project = json!({
+            "SelectedTimelineIndex": 0,
+            "Timelines": [{
+                "ID": 0,
+                "Name": "track2line",
+                "VideoInfo": {
+                    "FPS": fps,
+                    "Hz": self.audio_rate,
+                    "Width": self.width,
+                    "Height": self.height,
+                },
+                "Items": items,
+                "Length": length,
+                "MaxLayer": max_layer,
+            }],
+            "Characters": [],
+        });
+        serde_json::to_string_pretty(&project).map_err(Error::serialize)
+    }
+
+    /// ファイルを上書きで保存する
+    pub fn save_ymmp<P: AsRef<Path>>(&self, timeline: &Timeline, path: P) -> Result<(), Error> {
+        let content = self.to_ymmp(timeline).map_err(|e| e.with_path(&path))?;
+        fs::write(&path, content).map_err(Error::io(Operation::Write, &path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeline::Clip;
+    use std::{path::PathBuf, time::Duration};
+
+    #[test]
+    fn ymmp() {
+        let clip = |start: u64, line: &str, speaker: &str| Clip {
+            source: PathBuf::from("a.wav"),
+            path: PathBuf::from("a.wav"),
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(1000),
+            line: line.to_string(),
+            speaker: Some(speaker.to_string()),
+        };
+        let timeline = Timeline {
+            clips: vec![
+                clip(0, "こんにちは", "つくよみちゃん"),
+                clip(1500, "なのだ", "ずんだもん"),
+            ],
+        };
+        let ymmp = Ymm4Export::default().to_ymmp(&timeline).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&ymmp).unwrap();
+        let items = value["Timelines"][0]["Items"].as_array().unwrap();
+        assert_eq!(items.len(), 4);
+        assert_eq!(
+            items[0]["FilePath"].as_str().unwrap(),
+            path::absolute("a.wav").unwrap().to_string_lossy()
+        );
+        assert_eq!(items[2]["Frame"], 45);
+        assert_eq!(items[2]["Layer"], 2);
+        assert_eq!(items[3]["Text"], "なのだ");
+        assert_eq!(items[3]["Layer"], 3);
+        assert_eq!(value["Timelines"][0]["Length"], 75);
+    }
+}